@@ -211,6 +211,7 @@ fn main() {
     let font = Font::from_bytes(font_data).unwrap();
 
     let mut glyph_brush = GlyphBrush::new(&device, subpass.clone()).unwrap();
+    let font_id = glyph_brush.add_font(font.clone());
 
     let mut framebuffers: Option<Vec<Arc<vulkano::framebuffer::Framebuffer<_, _>>>> = None;
     let mut recreate_swapchain = false;
@@ -218,12 +219,12 @@ fn main() {
 
     let section1 = glyph_brush.queue_glyphs(
         font.layout("Hello, world!", Scale::uniform(100.0), point(300.0, 450.0)),
-        0,
+        font_id,
         [0.0, 0.0, 1.0, 1.0],
     );
     let section2 = glyph_brush.queue_glyphs(
         font.layout("Lower!", Scale::uniform(100.0), point(300.0, 500.0)),
-        0,
+        font_id,
         [0.0, 1.0, 0.0, 1.0],
     );
 
@@ -332,7 +333,6 @@ fn main() {
                     [0.0, 0.0, 1.0, 0.0],
                     [0.0, 0.0, 0.0, 1.0],
                 ],
-                [dimensions[0] as f32, dimensions[1] as f32],
             )
             .unwrap();
         let command_buffer = draw_triangle(command_buffer, &state);
@@ -347,7 +347,6 @@ fn main() {
                     [0.0, 0.0, 1.0, 0.0],
                     [0.0, 0.0, 0.0, 1.0],
                 ],
-                [dimensions[0] as f32, dimensions[1] as f32],
             )
             .unwrap();
 
@@ -0,0 +1,120 @@
+//! Build a brush with a custom fragment shader without opening a window, then draw some text into
+//! an image with the built-in shaders. vulkano only checks that the interfaces of two shaders
+//! match when a pipeline is created, so this fails if either pair of shaders is mismatched.
+
+use std::sync::Arc;
+
+use rusttype::Scale;
+use vulkano::device::{Device, DeviceExtensions, Features};
+use vulkano::format::Format;
+use vulkano::framebuffer::{RenderPassAbstract, Subpass};
+use vulkano::instance::{Instance, InstanceExtensions, PhysicalDevice};
+use vulkano::single_pass_renderpass;
+use vulkano::sync::GpuFuture;
+use vulkano_glyph::{GlyphBrush, Layout};
+
+static FONT: &[u8] = include_bytes!("../tests/fonts/DejaVuSansMono.ttf");
+
+// Tints the text with the parameters of its section. The fragment shader must declare every
+// output of the built-in vertex shader and read each of them, even those it has no use for.
+mod fs {
+    vulkano_shaders::shader! {
+        ty: "fragment",
+        src: "
+#version 450
+
+layout(set = 0, binding = 0) uniform sampler2D font_tex;
+
+layout(location = 0) in vec2 f_tex_pos;
+layout(location = 1) in vec4 f_color;
+layout(location = 2) flat in vec4 f_tex_rect;
+layout(location = 3) flat in vec4 f_outline_color;
+layout(location = 4) flat in float f_outline_width;
+layout(location = 5) flat in float f_blur;
+layout(location = 6) in vec2 f_pos;
+layout(location = 7) flat in uint f_gradient_kind;
+layout(location = 8) flat in vec4 f_gradient;
+layout(location = 9) flat in vec4 f_gradient_color;
+layout(location = 10) flat in uint f_kind;
+layout(location = 11) flat in vec4 f_params;
+
+layout(location = 0) out vec4 Target0;
+
+void main() {
+    vec4 unused = f_tex_rect + f_outline_color + f_gradient + f_gradient_color
+        + vec4(f_outline_width, f_blur, f_pos) + vec4(f_gradient_kind);
+
+    float coverage = f_kind == 1u ? 1.0 : texture(font_tex, f_tex_pos).r;
+    Target0 = vec4(mix(f_color.rgb, f_params.rgb, f_params.a), f_color.a * coverage);
+}
+"
+    }
+}
+
+fn main() {
+    let instance = Instance::new(None, &InstanceExtensions::none(), None)
+        .expect("failed to create Vulkan instance");
+    let physical = PhysicalDevice::enumerate(&instance)
+        .next()
+        .expect("no device available");
+    let family = physical
+        .queue_families()
+        .find(|family| family.supports_graphics())
+        .expect("couldn't find a graphical queue family");
+    let (device, mut queues) = Device::new(
+        physical,
+        &Features::none(),
+        &DeviceExtensions::none(),
+        [(family, 0.5)].iter().cloned(),
+    )
+    .expect("failed to create device");
+    let queue = queues.next().unwrap();
+
+    let render_pass = Arc::new(
+        single_pass_renderpass!(device.clone(),
+            attachments: {
+                color: {
+                    load: Clear,
+                    store: Store,
+                    format: Format::R8G8B8A8Unorm,
+                    samples: 1,
+                }
+            },
+            pass: {
+                color: [color],
+                depth_stencil: {}
+            }
+        )
+        .unwrap(),
+    );
+    let subpass =
+        Subpass::from(render_pass as Arc<dyn RenderPassAbstract + Send + Sync>, 0).unwrap();
+
+    let fs = fs::Shader::load(device.clone()).expect("failed to create shader module");
+    let mut glyph_brush = GlyphBrush::with_fragment_shader(&device, subpass, fs.main_entry_point())
+        .expect("failed to create glyph brush");
+    let font = glyph_brush.add_font_bytes(FONT).unwrap();
+
+    let layout = Layout::new(Scale::uniform(32.0), [8.0, 8.0]);
+    let section = glyph_brush
+        .queue_text("Hello, world!", font, &layout, [0.0, 0.0, 0.0, 1.0])
+        .with_params([1.0, 0.0, 0.0, 0.5]);
+
+    if let Some(future) = glyph_brush.cache_sections(&queue, Some(&section)).unwrap() {
+        future
+            .then_signal_fence_and_flush()
+            .unwrap()
+            .wait(None)
+            .unwrap();
+    }
+    let (_image, future) = glyph_brush
+        .draw_to_image(&queue, Some(&section), [256, 64])
+        .unwrap();
+    future
+        .then_signal_fence_and_flush()
+        .unwrap()
+        .wait(None)
+        .unwrap();
+
+    println!("Created a brush with a custom fragment shader and drew text into an image");
+}
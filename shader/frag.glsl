@@ -1,16 +1,118 @@
 #version 450
 
-layout(set = 0, binding = 1) uniform sampler2D font_tex;
+layout(set = 0, binding = 0) uniform sampler2D font_tex;
 
 layout(location = 0) in vec2 f_tex_pos;
 layout(location = 1) in vec4 f_color;
+layout(location = 2) flat in vec4 f_tex_rect;
+layout(location = 3) flat in vec4 f_outline_color;
+layout(location = 4) flat in float f_outline_width;
+layout(location = 5) flat in float f_blur;
+layout(location = 6) in vec2 f_pos;
+layout(location = 7) flat in uint f_gradient_kind;
+layout(location = 8) flat in vec4 f_gradient;
+layout(location = 9) flat in vec4 f_gradient_color;
+layout(location = 10) flat in uint f_kind;
+layout(location = 11) flat in vec4 f_params;
 
 layout(location = 0) out vec4 Target0;
 
+const uint KIND_GLYPH = 0u;
+const uint KIND_RECT = 1u;
+
+const uint GRADIENT_NONE = 0u;
+const uint GRADIENT_LINEAR = 1u;
+const uint GRADIENT_RADIAL = 2u;
+
+// Sample the glyph coverage, ignoring any neighbouring glyphs in the cache. Solid rectangles
+// are fully covered.
+float coverage(vec2 pos) {
+    if (any(lessThan(pos, f_tex_rect.xy)) || any(greaterThan(pos, f_tex_rect.zw))) {
+        return 0.0;
+    }
+    if (f_kind == KIND_RECT) {
+        return 1.0;
+    }
+    return texture(font_tex, pos).r;
+}
+
+// The maximum coverage within `radius` texels of `pos`.
+float dilated_coverage(vec2 pos, float radius) {
+    vec2 texel = 1.0 / vec2(textureSize(font_tex, 0));
+    int r = int(ceil(radius));
+    float result = 0.0;
+    for (int y = -r; y <= r; y++) {
+        for (int x = -r; x <= r; x++) {
+            vec2 offset = vec2(x, y);
+            float weight = clamp(radius + 1.0 - length(offset), 0.0, 1.0);
+            result = max(result, weight * coverage(pos + offset * texel));
+        }
+    }
+    return result;
+}
+
+// The coverage at `pos` with a gaussian blur of `radius` texels applied.
+float blurred_coverage(vec2 pos, float radius) {
+    vec2 texel = 1.0 / vec2(textureSize(font_tex, 0));
+    int r = int(ceil(radius));
+    float sigma = max(radius / 2.0, 0.5);
+    float total = 0.0;
+    float total_weight = 0.0;
+    for (int y = -r; y <= r; y++) {
+        for (int x = -r; x <= r; x++) {
+            vec2 offset = vec2(x, y);
+            float weight = exp(-dot(offset, offset) / (2.0 * sigma * sigma));
+            total += weight * coverage(pos + offset * texel);
+            total_weight += weight;
+        }
+    }
+    return total / total_weight;
+}
+
+// The fill colour at the current pixel.
+vec4 fill_color() {
+    float t;
+    switch (f_gradient_kind) {
+        case GRADIENT_LINEAR: {
+            vec2 dir = f_gradient.zw - f_gradient.xy;
+            t = dot(f_pos - f_gradient.xy, dir) / max(dot(dir, dir), 1e-6);
+            break;
+        }
+        case GRADIENT_RADIAL:
+            t = length((f_pos - f_gradient.xy) / max(f_gradient.zw, vec2(1e-6)));
+            break;
+        default:
+            return f_color;
+    }
+    return mix(f_color, f_gradient_color, clamp(t, 0.0, 1.0));
+}
+
 void main() {
-    float alpha = texture(font_tex, f_tex_pos).r;
+    // The built-in shader has no use for the custom parameters, but it must read them so that the
+    // input stays in its interface, which has to match the outputs of the vertex shader.
+    vec4 params = f_params;
+
+    if (f_blur > 0.0) {
+        float alpha = blurred_coverage(f_tex_pos, f_blur) * f_color.a;
+        if (alpha <= 0.0) {
+            discard;
+        }
+        Target0 = vec4(f_color.rgb, alpha);
+        return;
+    }
+
+    vec4 color = fill_color();
+    float fill = coverage(f_tex_pos) * color.a;
+    float stroke = 0.0;
+    if (f_outline_width > 0.0) {
+        stroke = dilated_coverage(f_tex_pos, f_outline_width) * f_outline_color.a;
+    }
+
+    // Composite the fill over the outline.
+    float alpha = fill + stroke * (1.0 - fill);
     if (alpha <= 0.0) {
         discard;
     }
-    Target0 = f_color * vec4(1.0, 1.0, 1.0, alpha);
+    vec3 rgb = (color.rgb * fill + f_outline_color.rgb * stroke * (1.0 - fill)) / alpha;
+    Target0 = vec4(rgb, alpha);
 }
@@ -1,40 +1,88 @@
 #version 450
 
-layout(set = 0, binding = 0) uniform Data {
-    uniform mat4 transform;
+layout(push_constant) uniform PushConstants {
+    mat4 transform;
 } uniforms;
 
+layout(set = 0, binding = 0) uniform sampler2D font_tex;
+
 layout(location = 0) in vec2 tl;
 layout(location = 1) in vec2 br;
 layout(location = 2) in vec2 tex_tl;
 layout(location = 3) in vec2 tex_br;
-layout(location = 4) in vec4 color;
+layout(location = 4) in uint kind;
+layout(location = 5) in vec4 color;
+layout(location = 6) in vec4 outline_color;
+layout(location = 7) in float outline_width;
+layout(location = 8) in float blur;
+layout(location = 9) in uint gradient_kind;
+layout(location = 10) in vec4 gradient;
+layout(location = 11) in vec4 gradient_color;
+layout(location = 12) in vec4 params;
+layout(location = 13) in uint rotated;
 
 layout(location = 0) out vec2 f_tex_pos;
 layout(location = 1) out vec4 f_color;
+layout(location = 2) flat out vec4 f_tex_rect;
+layout(location = 3) flat out vec4 f_outline_color;
+layout(location = 4) flat out float f_outline_width;
+layout(location = 5) flat out float f_blur;
+layout(location = 6) out vec2 f_pos;
+layout(location = 7) flat out uint f_gradient_kind;
+layout(location = 8) flat out vec4 f_gradient;
+layout(location = 9) flat out vec4 f_gradient_color;
+layout(location = 10) flat out uint f_kind;
+layout(location = 11) flat out vec4 f_params;
 
 void main() {
     vec2 pos;
 
+    // The quad has been grown to make room for the outline or blur, so extend the texture
+    // coordinates to match. One pixel on screen corresponds to one texel in the cache.
+    vec2 pad = vec2(ceil(outline_width) + ceil(blur)) / vec2(textureSize(font_tex, 0));
+    vec2 uv_tl = tex_tl - pad;
+    vec2 uv_br = tex_br + pad;
+
     switch (gl_VertexIndex) {
         case 0: // bottom left 
             pos = vec2(tl.x, br.y);
-            f_tex_pos = vec2(tex_tl.x, tex_br.y);
+            f_tex_pos = vec2(uv_tl.x, uv_br.y);
             break;
         case 1: // top left
             pos = tl;
-            f_tex_pos = tex_tl;
+            f_tex_pos = uv_tl;
             break;
         case 2: // bottom right
             pos = br;
-            f_tex_pos = tex_br;
+            f_tex_pos = uv_br;
             break;
         case 3: // top right
             pos = vec2(br.x, tl.y);
-            f_tex_pos = vec2(tex_br.x, tex_tl.y);
+            f_tex_pos = vec2(uv_br.x, uv_tl.y);
             break;
     }
 
+    // A rotated glyph is turned clockwise, so each corner of the quad samples the corner of the
+    // glyph a quarter turn anticlockwise from it.
+    if (rotated != 0) {
+        switch (gl_VertexIndex) {
+            case 0: f_tex_pos = uv_br; break;
+            case 1: f_tex_pos = vec2(uv_tl.x, uv_br.y); break;
+            case 2: f_tex_pos = vec2(uv_br.x, uv_tl.y); break;
+            case 3: f_tex_pos = uv_tl; break;
+        }
+    }
+
     f_color = color;
+    f_tex_rect = vec4(tex_tl, tex_br);
+    f_outline_color = outline_color;
+    f_outline_width = outline_width;
+    f_blur = blur;
+    f_pos = pos;
+    f_gradient_kind = gradient_kind;
+    f_gradient = gradient;
+    f_gradient_color = gradient_color;
+    f_kind = kind;
+    f_params = params;
     gl_Position = uniforms.transform * vec4(pos, 0.0, 1.0);
 }
@@ -0,0 +1,47 @@
+use std::sync::Arc;
+
+use vulkano::buffer::ImmutableBuffer;
+
+use crate::draw::TextVertex;
+use crate::section::QueuedGlyph;
+use crate::Section;
+
+/// Text which is retained between frames. The vertices for its glyphs are kept in a device-local
+/// buffer, and are only rebuilt when the glyph cache changes.
+///
+/// A `TextBuffer` is created with `GlyphBrush::text_buffer`, and must be passed to
+/// `GlyphBrush::cache_text_buffers` before each time it is drawn, since caching other text may
+/// evict its glyphs. Calling `GlyphBrush::cache_sections` makes every buffer outdated.
+pub struct TextBuffer<'font> {
+    pub(crate) glyphs: Vec<QueuedGlyph<'font>>,
+    pub(crate) sections: Vec<Section>,
+    pub(crate) vbuf: Option<Arc<ImmutableBuffer<[TextVertex]>>>,
+    pub(crate) generation: u64,
+    pub(crate) cached: Option<u64>,
+}
+
+impl<'font> TextBuffer<'font> {
+    pub(crate) fn new<'a, I>(glyphs: &[QueuedGlyph<'font>], sections: I) -> Self
+    where
+        I: IntoIterator<Item = &'a Section>,
+    {
+        let mut buffer = TextBuffer {
+            glyphs: Vec::new(),
+            sections: Vec::new(),
+            vbuf: None,
+            generation: 0,
+            cached: None,
+        };
+        for section in sections {
+            let start = buffer.glyphs.len();
+            buffer
+                .glyphs
+                .extend_from_slice(&glyphs[section.range.clone()]);
+            buffer.sections.push(Section {
+                range: start..buffer.glyphs.len(),
+                ..section.clone()
+            });
+        }
+        buffer
+    }
+}
@@ -1,7 +1,7 @@
 use std::sync::Arc;
 use std::{iter, result};
 
-use rusttype::gpu_cache::{Cache, CacheReadErr, CacheWriteErr, TextureCoords};
+use rusttype::gpu_cache::{Cache, CacheReadErr, CacheWriteErr, CachedBy, TextureCoords};
 use rusttype::{PositionedGlyph, Rect};
 use vulkano::buffer::CpuBufferPool;
 use vulkano::command_buffer::{
@@ -12,7 +12,7 @@ use vulkano::format::R8Unorm;
 use vulkano::image::{Dimensions, ImageUsage, StorageImage};
 use vulkano::sync::NowFuture;
 
-use crate::{FontId, Result};
+use crate::Result;
 
 const INITIAL_WIDTH: u32 = 256;
 const INITIAL_HEIGHT: u32 = 256;
@@ -22,6 +22,7 @@ pub struct GpuCache<'font> {
     cache: Cache<'font>,
     img: Arc<StorageImage<R8Unorm>>,
     buf: CpuBufferPool<u8>,
+    generation: u64,
 }
 
 impl<'font> GpuCache<'font> {
@@ -33,25 +34,35 @@ impl<'font> GpuCache<'font> {
             .dimensions(INITIAL_WIDTH, INITIAL_HEIGHT)
             .build();
 
-        Ok(GpuCache { cache, img, buf })
+        Ok(GpuCache {
+            cache,
+            img,
+            buf,
+            generation: 0,
+        })
     }
 
-    /// Overwrite the cache with a new collection of glyphs. If the cache is too small, it
-    /// will be resized until it is big enough.
+    /// Overwrite the cache with a new collection of glyphs, each paired with the index of its
+    /// font. If the cache is too small, it will be resized until it is big enough.
     pub fn cache<I>(
         &mut self,
         queue: &Arc<Queue>,
         glyphs: I,
     ) -> Result<Option<CommandBufferExecFuture<NowFuture, AutoCommandBuffer>>>
     where
-        I: IntoIterator<Item = (FontId, PositionedGlyph<'font>)>,
+        I: IntoIterator<Item = (usize, PositionedGlyph<'font>)>,
     {
         for (font, gly) in glyphs {
             self.cache.queue_glyph(font, gly);
         }
 
         let mut result = Ok(None);
-        while let Err(write_err) = self.try_cache(queue, &mut result) {
+        let cached_by = loop {
+            let write_err = match self.try_cache(queue, &mut result) {
+                Ok(cached_by) => break cached_by,
+                Err(write_err) => write_err,
+            };
+
             let (old_w, old_h) = self.cache.dimensions();
             let (new_w, new_h) = (old_w * 2, old_h * 2);
             // Cache too small, grow it and retry.
@@ -68,6 +79,11 @@ impl<'font> GpuCache<'font> {
                 .dimensions(new_w, new_h)
                 .rebuild(&mut self.cache);
             self.img = create_image(queue.device(), new_w, new_h)?;
+            self.generation += 1;
+        };
+
+        if cached_by == CachedBy::Reordering {
+            self.generation += 1;
         }
 
         result.and_then(|cmd| {
@@ -82,8 +98,10 @@ impl<'font> GpuCache<'font> {
         &mut self,
         queue: &Arc<Queue>,
         result: &mut Result<Option<AutoCommandBufferBuilder>>,
-    ) -> result::Result<(), CacheWriteErr> {
-        let GpuCache { cache, buf, img } = self;
+    ) -> result::Result<CachedBy, CacheWriteErr> {
+        let GpuCache {
+            cache, buf, img, ..
+        } = self;
         cache.cache_queued(|rect, data| {
             let cmd = match result {
                 Ok(cmd) => cmd.take(),
@@ -91,19 +109,26 @@ impl<'font> GpuCache<'font> {
             };
 
             *result = upload(rect, data, queue, cmd, img, buf).map(Some);
-        })?;
-        Ok(())
+        })
     }
 
-    /// Get the coordinates of a glyph on the image.
+    /// Get the coordinates of a glyph on the image. Glyphs cached by a `GlyphBrush` are stored
+    /// under the index of their font, given by `FontId::index`.
     pub fn rect_for(
         &self,
-        font_id: FontId,
+        font_id: usize,
         glyph: &PositionedGlyph<'_>,
     ) -> result::Result<Option<TextureCoords>, CacheReadErr> {
         self.cache.rect_for(font_id, glyph)
     }
 
+    /// A counter which is incremented whenever glyphs already in the cache may have moved, for
+    /// example because the cache was resized. Texture coordinates obtained from `rect_for` remain
+    /// valid while this is unchanged, for glyphs included in the latest call to `cache`.
+    pub fn generation(&self) -> u64 {
+        self.generation
+    }
+
     /// The GPU image containing cached glyphs.
     pub fn image(&self) -> &Arc<StorageImage<R8Unorm>> {
         &self.img
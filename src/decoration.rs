@@ -0,0 +1,189 @@
+use std::ops::Range;
+
+use rusttype::{point, Rect};
+
+use crate::section::QueuedGlyph;
+use crate::RegisteredFont;
+
+/// Lines drawn across the text in a `Section`.
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq)]
+pub struct Decorations {
+    /// Draw a line below the baseline.
+    pub underline: bool,
+    /// Draw a line through the middle of lowercase letters.
+    pub strikethrough: bool,
+    /// Draw a line along the ascent of the font.
+    pub overline: bool,
+}
+
+impl Decorations {
+    /// No decorations.
+    pub fn none() -> Self {
+        Decorations::default()
+    }
+
+    /// Whether any decoration is enabled.
+    pub fn is_empty(&self) -> bool {
+        !(self.underline || self.strikethrough || self.overline)
+    }
+}
+
+/// The position of the top of the underline and strikeout of a font and their thickness, in
+/// unscaled font units with y pointing up, as given by its `post` and `OS/2` tables.
+#[derive(Copy, Clone, Debug, Default)]
+pub(crate) struct LineMetrics {
+    pub(crate) underline: Option<(f32, f32)>,
+    pub(crate) strikeout: Option<(f32, f32)>,
+}
+
+impl LineMetrics {
+    /// Read the metrics from the raw data of a font. `rusttype` does not expose these tables, so
+    /// this needs the data to be parsed again.
+    pub(crate) fn from_data(data: &[u8]) -> Self {
+        let face = match ttf_parser::Face::from_slice(data, 0) {
+            Ok(face) => face,
+            Err(_) => return LineMetrics::default(),
+        };
+        let line = |metrics: ttf_parser::LineMetrics| {
+            (f32::from(metrics.position), f32::from(metrics.thickness))
+        };
+        LineMetrics {
+            underline: face.underline_metrics().map(line),
+            strikeout: face.strikeout_metrics().map(line),
+        }
+    }
+}
+
+/// Get the rectangles, in pixels, covered by the decorations of some glyphs, along with the
+/// baseline and colour of the glyphs they are drawn across, if overridden. Glyphs with their own
+/// decorations use those instead of `decorations`.
+///
+/// Underlines and strikethroughs use the line metrics of the font where they are known, which
+/// requires a font added with `GlyphBrush::add_font_bytes`. Otherwise, and for overlines, the
+/// positions and thickness are derived from its ascent and descent.
+pub(crate) fn decoration_rects(
+    decorations: Decorations,
+    glyphs: &[QueuedGlyph<'_>],
+    fonts: &[RegisteredFont<'_>],
+) -> Vec<(Rect<f32>, f32, Option<[f32; 4]>)> {
+    let mut rects = Vec::new();
+    for run in runs(glyphs) {
+        let decorations = run[0].decorations.unwrap_or(decorations);
+        if decorations.is_empty() {
+            continue;
+        }
+        let first = &run[0].glyph;
+        let font = &fonts[run[0].font.index];
+        let v_metrics = font.font.v_metrics(first.scale());
+        let baseline = first.position().y;
+        let (left, right) = extent(run);
+
+        // Font units are scaled the same way as `rusttype`, which maps the height from descent
+        // to ascent onto the scale.
+        let unscaled = font.font.v_metrics_unscaled();
+        let units = first.scale().y / (unscaled.ascent - unscaled.descent);
+        let from_table = |(position, thickness): (f32, f32)| {
+            let thickness = (thickness * units).max(1.0);
+            (baseline - position * units + thickness / 2.0, thickness)
+        };
+        let fallback = ((v_metrics.ascent - v_metrics.descent) / 20.0).max(1.0);
+        let underline = font.line_metrics.underline.map(from_table);
+        let strikeout = font.line_metrics.strikeout.map(from_table);
+
+        let color = run[0].color;
+        let mut line = |(center, thickness): (f32, f32)| {
+            let rect = Rect {
+                min: point(left, center - thickness / 2.0),
+                max: point(right, center + thickness / 2.0),
+            };
+            rects.push((rect, baseline, color));
+        };
+
+        if decorations.underline {
+            line(underline.unwrap_or((baseline - v_metrics.descent * 0.4, fallback)));
+        }
+        if decorations.strikethrough {
+            line(strikeout.unwrap_or((baseline - v_metrics.ascent * 0.3, fallback)));
+        }
+        if decorations.overline {
+            let thickness = underline.map_or(fallback, |(_, thickness)| thickness);
+            line((baseline - v_metrics.ascent, thickness));
+        }
+    }
+    rects
+}
+
+/// Get the rectangles, in pixels, spanning the line height and advance widths of the glyphs laid
+/// out from a range of text, along with their baselines. Glyphs are highlighted if their source
+/// overlaps the range, and adjacent highlighted glyphs share a rectangle.
+pub(crate) fn highlight_rects(
+    glyphs: &[QueuedGlyph<'_>],
+    range: &Range<usize>,
+) -> Vec<(Rect<f32>, f32)> {
+    glyphs
+        .split(|gly| gly.source.start >= range.end || gly.source.end <= range.start)
+        .flat_map(runs)
+        .filter_map(|run| {
+            let first = &run[0].glyph;
+            let v_metrics = first.font()?.v_metrics(first.scale());
+            let (left, right) = extent(run);
+            let baseline = first.position().y;
+            let rect = Rect {
+                min: point(left, baseline - v_metrics.ascent),
+                max: point(right, baseline - v_metrics.descent),
+            };
+            Some((rect, baseline))
+        })
+        .collect()
+}
+
+/// Get the horizontal extent of a run of glyphs, including the advance of the last glyph.
+fn extent(run: &[QueuedGlyph<'_>]) -> (f32, f32) {
+    let left = run[0].glyph.position().x;
+    let right = run
+        .iter()
+        .map(|gly| gly.glyph.position().x + gly.glyph.unpositioned().h_metrics().advance_width)
+        .fold(left, f32::max);
+    (left, right)
+}
+
+/// Split glyphs into runs sharing the same baseline, scale, colour and decorations. Glyphs of
+/// vertical text are left out, since each would be a run of its own.
+fn runs<'a, 'font>(glyphs: &'a [QueuedGlyph<'font>]) -> Vec<&'a [QueuedGlyph<'font>]> {
+    let mut runs = Vec::new();
+    for mut rest in glyphs.split(|gly| gly.vertical) {
+        while let Some(first) = rest.first() {
+            let len = rest
+                .iter()
+                .position(|gly| {
+                    gly.glyph.position().y != first.glyph.position().y
+                        || gly.glyph.scale() != first.glyph.scale()
+                        || gly.color != first.color
+                        || gly.decorations != first.decorations
+                })
+                .unwrap_or(rest.len());
+            let (run, tail) = rest.split_at(len);
+            runs.push(run);
+            rest = tail;
+        }
+    }
+    runs
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn line_metrics_are_read_from_the_font_tables() {
+        let data = include_bytes!("../tests/fonts/DejaVuSansMono.ttf");
+        let metrics = LineMetrics::from_data(data);
+        let (position, thickness) = metrics.underline.unwrap();
+        assert!(position < 0.0 && thickness > 0.0);
+        let (position, thickness) = metrics.strikeout.unwrap();
+        assert!(position > 0.0 && thickness > 0.0);
+
+        let metrics = LineMetrics::from_data(b"not a font");
+        assert!(metrics.underline.is_none() && metrics.strikeout.is_none());
+    }
+}
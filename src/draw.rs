@@ -1,30 +1,126 @@
 use std::iter;
 use std::sync::Arc;
 
-use rusttype::PositionedGlyph;
-use vulkano::buffer::{BufferUsage, CpuBufferPool};
+use rusttype::{point, Rect};
+use vulkano::buffer::{BufferAccess, BufferUsage, CpuBufferPool, TypedBufferAccess};
 use vulkano::command_buffer::{AutoCommandBufferBuilder, DrawIndirectCommand, DynamicState};
-use vulkano::descriptor::descriptor_set::FixedSizeDescriptorSetsPool;
-use vulkano::descriptor::PipelineLayoutAbstract;
-use vulkano::device::Device;
+use vulkano::descriptor::descriptor_set::PersistentDescriptorSet;
+use vulkano::descriptor::{DescriptorSet, PipelineLayoutAbstract};
+use vulkano::device::{Device, DeviceOwned};
+use vulkano::format::R8Unorm;
 use vulkano::framebuffer::{RenderPassAbstract, Subpass};
+use vulkano::image::StorageImage;
 use vulkano::impl_vertex;
+use vulkano::instance::QueueFamily;
+use vulkano::pipeline::shader::GraphicsEntryPointAbstract;
 use vulkano::pipeline::vertex::SingleInstanceBufferDefinition;
+use vulkano::pipeline::viewport::Viewport;
 use vulkano::pipeline::GraphicsPipeline;
 use vulkano::sampler::{Filter, MipmapMode, Sampler, SamplerAddressMode};
 
-use crate::{Error, GpuCache, Section};
+use crate::decoration::{decoration_rects, highlight_rects};
+use crate::section::QueuedGlyph;
+use crate::{Error, ErrorKind, GpuCache, Gradient, GradientSpace, RegisteredFont, Section};
 
-#[derive(Debug)]
-struct Vertex {
-    tl: [f32; 2],
-    br: [f32; 2],
-    tex_tl: [f32; 2],
-    tex_br: [f32; 2],
-    color: [f32; 4],
+/// The data for one quad of text, either a glyph or a solid rectangle.
+///
+/// Each `TextVertex` is one instance of a four vertex triangle strip. The layout matches the
+/// inputs of the vertex shader described in `GlyphBrush::with_shaders`, so it can be used with
+/// `SingleInstanceBufferDefinition` in a custom pipeline, sampling `GpuCache::image`.
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct TextVertex {
+    /// The top left corner of the quad, in pixels.
+    pub tl: [f32; 2],
+    /// The bottom right corner of the quad, in pixels.
+    pub br: [f32; 2],
+    /// The top left corner of the glyph in the cache, in normalized texture coordinates.
+    pub tex_tl: [f32; 2],
+    /// The bottom right corner of the glyph in the cache, in normalized texture coordinates.
+    pub tex_br: [f32; 2],
+    /// 0 for a glyph, 1 for a solid rectangle which does not sample the cache.
+    pub kind: u32,
+    /// The fill colour, or the first gradient colour.
+    pub color: [f32; 4],
+    /// The outline colour.
+    pub outline_color: [f32; 4],
+    /// The outline width, in pixels. The quad has been grown by this amount, rounded up, on each
+    /// side, but the texture coordinates have not.
+    pub outline_width: f32,
+    /// The blur radius of a shadow, in pixels. The quad has been grown by this amount, rounded
+    /// up, on each side, but the texture coordinates have not.
+    pub blur: f32,
+    /// 0 for none, 1 for linear, 2 for radial.
+    pub gradient_kind: u32,
+    /// The start and end points of a linear gradient, or the centre and radii of a radial
+    /// gradient, in pixels.
+    pub gradient: [f32; 4],
+    /// The second gradient colour.
+    pub gradient_color: [f32; 4],
+    /// The parameters set by `Section::with_params`.
+    pub params: [f32; 4],
+    /// 1 if the glyph is rotated a quarter turn clockwise within the quad, for vertical text, so
+    /// the top left corner of the glyph in the cache maps to the top right corner of the quad.
+    pub rotated: u32,
 }
 
-impl_vertex! { Vertex, tl, br, tex_tl, tex_br, color }
+impl_vertex! {
+    TextVertex,
+    tl,
+    br,
+    tex_tl,
+    tex_br,
+    kind,
+    color,
+    outline_color,
+    outline_width,
+    blur,
+    gradient_kind,
+    gradient,
+    gradient_color,
+    params,
+    rotated
+}
+
+const KIND_GLYPH: u32 = 0;
+const KIND_RECT: u32 = 1;
+
+const GRADIENT_NONE: u32 = 0;
+const GRADIENT_LINEAR: u32 = 1;
+const GRADIENT_RADIAL: u32 = 2;
+
+/// The coordinate system in which glyphs are positioned and the transform is applied.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum Coordinates {
+    /// Pixels from the top left corner of the framebuffer, with y pointing down. Positions are
+    /// mapped to normalized device coordinates within the viewport before the transform is
+    /// applied. This is the default.
+    TopLeft,
+    /// Pixels with y pointing up, offset by the origin of the viewport in the same way as
+    /// `TopLeft`, so the bottom left corner of the viewport is at its origin. Positions are mapped
+    /// to normalized device coordinates within the viewport before the transform is applied.
+    BottomLeft,
+    /// Pixels which are passed to the transform unchanged, so it must map them to normalized
+    /// device coordinates itself. If `y_up` is true, glyphs are drawn upright in a space where y
+    /// points up.
+    Raw { y_up: bool },
+}
+
+impl Coordinates {
+    /// Whether the y axis points up.
+    pub fn y_up(self) -> bool {
+        match self {
+            Coordinates::TopLeft => false,
+            Coordinates::BottomLeft => true,
+            Coordinates::Raw { y_up } => y_up,
+        }
+    }
+}
+
+impl Default for Coordinates {
+    fn default() -> Self {
+        Coordinates::TopLeft
+    }
+}
 
 #[allow(unused)]
 mod vs {
@@ -44,7 +140,7 @@ mod fs {
 
 type Pipeline = Arc<
     GraphicsPipeline<
-        SingleInstanceBufferDefinition<Vertex>,
+        SingleInstanceBufferDefinition<TextVertex>,
         Box<dyn PipelineLayoutAbstract + Send + Sync>,
         Arc<dyn RenderPassAbstract + Send + Sync>,
     >,
@@ -52,11 +148,16 @@ type Pipeline = Arc<
 
 pub(crate) struct Draw {
     pipe: Pipeline,
-    vbuf: CpuBufferPool<Vertex>,
-    ubuf: CpuBufferPool<vs::ty::Data>,
-    pool: FixedSizeDescriptorSetsPool<Pipeline>,
+    vbuf: CpuBufferPool<TextVertex>,
     sampler: Arc<Sampler>,
     ibuf: CpuBufferPool<DrawIndirectCommand>,
+    subpass: Subpass<Arc<dyn RenderPassAbstract + Send + Sync>>,
+    coordinates: Coordinates,
+    /// The descriptor set binding the glyph cache, along with the image it was created for.
+    set: Option<(
+        Arc<StorageImage<R8Unorm>>,
+        Arc<dyn DescriptorSet + Send + Sync>,
+    )>,
 }
 
 impl Draw {
@@ -66,25 +167,54 @@ impl Draw {
     ) -> Result<Self, Error> {
         let vs = vs::Shader::load(Arc::clone(device))?;
         let fs = fs::Shader::load(Arc::clone(device))?;
+        Draw::with_shaders(
+            device,
+            subpass,
+            vs.main_entry_point(),
+            fs.main_entry_point(),
+        )
+    }
 
+    pub(crate) fn with_fragment_shader<Fs>(
+        device: &Arc<Device>,
+        subpass: Subpass<Arc<dyn RenderPassAbstract + Send + Sync>>,
+        fs: Fs,
+    ) -> Result<Self, Error>
+    where
+        Fs: GraphicsEntryPointAbstract<SpecializationConstants = ()>,
+        Fs::PipelineLayout: Clone + Send + Sync + 'static,
+    {
+        let vs = vs::Shader::load(Arc::clone(device))?;
+        Draw::with_shaders(device, subpass, vs.main_entry_point(), fs)
+    }
+
+    pub(crate) fn with_shaders<Vs, Fs>(
+        device: &Arc<Device>,
+        subpass: Subpass<Arc<dyn RenderPassAbstract + Send + Sync>>,
+        vs: Vs,
+        fs: Fs,
+    ) -> Result<Self, Error>
+    where
+        Vs: GraphicsEntryPointAbstract<SpecializationConstants = ()>,
+        Vs::PipelineLayout: Clone + Send + Sync + 'static,
+        Fs: GraphicsEntryPointAbstract<SpecializationConstants = ()>,
+        Fs::PipelineLayout: Clone + Send + Sync + 'static,
+    {
         let pipe = Arc::new(
             GraphicsPipeline::start()
                 .blend_alpha_blending()
-                .vertex_input(SingleInstanceBufferDefinition::<Vertex>::new())
-                .vertex_shader(vs.main_entry_point(), ())
+                .vertex_input(SingleInstanceBufferDefinition::<TextVertex>::new())
+                .vertex_shader(vs, ())
                 .triangle_strip()
                 .viewports_dynamic_scissors_irrelevant(1)
-                .fragment_shader(fs.main_entry_point(), ())
-                .render_pass(subpass)
+                .fragment_shader(fs, ())
+                .render_pass(subpass.clone())
                 .build(Arc::clone(device))?,
         );
 
         let vbuf = CpuBufferPool::new(Arc::clone(device), BufferUsage::vertex_buffer());
-        let ubuf = CpuBufferPool::new(Arc::clone(device), BufferUsage::uniform_buffer());
         let ibuf = CpuBufferPool::new(Arc::clone(device), BufferUsage::indirect_buffer());
 
-        let pool = FixedSizeDescriptorSetsPool::new(Arc::clone(&pipe), 0);
-
         let sampler = Sampler::new(
             Arc::clone(device),
             Filter::Nearest,
@@ -102,80 +232,600 @@ impl Draw {
         Ok(Draw {
             pipe,
             vbuf,
-            ubuf,
-            pool,
             sampler,
             ibuf,
+            subpass,
+            coordinates: Coordinates::default(),
+            set: None,
         })
     }
 
+    pub(crate) fn set_coordinates(&mut self, coordinates: Coordinates) {
+        self.coordinates = coordinates;
+    }
+
+    pub(crate) fn device(&self) -> &Arc<Device> {
+        self.pipe.device()
+    }
+
+    /// Create a secondary command buffer which inherits the subpass of the pipeline.
+    pub(crate) fn secondary(
+        &self,
+        queue_family: QueueFamily<'_>,
+    ) -> Result<AutoCommandBufferBuilder, Error> {
+        Ok(
+            AutoCommandBufferBuilder::secondary_graphics_one_time_submit(
+                Arc::clone(self.device()),
+                queue_family,
+                self.subpass.clone(),
+            )?,
+        )
+    }
+
     pub(crate) fn draw<'a, 'font, I>(
         &mut self,
         cmd: AutoCommandBufferBuilder,
-        glyphs: &[PositionedGlyph<'font>],
+        glyphs: &[QueuedGlyph<'font>],
         sections: I,
+        fonts: &[RegisteredFont<'font>],
         cache: &GpuCache<'font>,
         dynamic_state: &DynamicState,
         transform: [[f32; 4]; 4],
-        dims: [f32; 2],
     ) -> Result<AutoCommandBufferBuilder, Error>
     where
         I: IntoIterator<Item = &'a Section>,
     {
-        let vertices = text_vertices(glyphs, sections, cache, dims)?;
+        let vertices = text_vertices(glyphs, sections, fonts, cache)?;
         let instance_count = vertices.len() as u32;
         let vbuf = self.vbuf.chunk(vertices)?;
-        let ubuf = self.ubuf.next(vs::ty::Data { transform })?;
+        self.draw_instances(cmd, vbuf, instance_count, cache, dynamic_state, transform)
+    }
+
+    /// Draw some vertices previously generated by `text_vertices`.
+    pub(crate) fn draw_instances<'font, V>(
+        &mut self,
+        cmd: AutoCommandBufferBuilder,
+        vbuf: V,
+        instance_count: u32,
+        cache: &GpuCache<'font>,
+        dynamic_state: &DynamicState,
+        transform: [[f32; 4]; 4],
+    ) -> Result<AutoCommandBufferBuilder, Error>
+    where
+        V: BufferAccess + TypedBufferAccess<Content = [TextVertex]> + Send + Sync + 'static,
+    {
+        let viewport = match &dynamic_state.viewports {
+            Some(viewports) if !viewports.is_empty() => &viewports[0],
+            _ => return Err(ErrorKind::MissingViewport.into()),
+        };
+        let push_constants = vs::ty::PushConstants {
+            transform: pixel_transform(transform, viewport, self.coordinates),
+        };
         let ibuf = self.ibuf.chunk(iter::once(DrawIndirectCommand {
             vertex_count: 4,
             instance_count,
             first_vertex: 0,
             first_instance: 0,
         }))?;
+        let set = self.descriptor_set(cache)?;
+
+        Ok(cmd.draw_indirect(
+            Arc::clone(&self.pipe),
+            dynamic_state,
+            vbuf,
+            ibuf,
+            set,
+            push_constants,
+        )?)
+    }
 
-        let set = self
-            .pool
-            .next()
-            .add_buffer(ubuf)?
-            .add_sampled_image(Arc::clone(cache.image()), Arc::clone(&self.sampler))?
-            .build()?;
+    /// Get the descriptor set binding the glyph cache, which is only recreated when the cache
+    /// image is replaced.
+    fn descriptor_set(
+        &mut self,
+        cache: &GpuCache<'_>,
+    ) -> Result<Arc<dyn DescriptorSet + Send + Sync>, Error> {
+        match &self.set {
+            Some((image, set)) if Arc::ptr_eq(image, cache.image()) => return Ok(Arc::clone(set)),
+            _ => (),
+        }
 
-        Ok(cmd.draw_indirect(Arc::clone(&self.pipe), dynamic_state, vbuf, ibuf, set, ())?)
+        let set = Arc::new(
+            PersistentDescriptorSet::start(Arc::clone(&self.pipe), 0)
+                .add_sampled_image(Arc::clone(cache.image()), Arc::clone(&self.sampler))?
+                .build()?,
+        ) as Arc<dyn DescriptorSet + Send + Sync>;
+        self.set = Some((Arc::clone(cache.image()), Arc::clone(&set)));
+        Ok(set)
     }
 }
 
-fn text_vertices<'a, 'font, I>(
-    glyphs: &[PositionedGlyph<'font>],
+pub(crate) fn text_vertices<'a, 'font, I>(
+    glyphs: &[QueuedGlyph<'font>],
     sections: I,
+    fonts: &[RegisteredFont<'font>],
     cache: &GpuCache<'font>,
-    [screen_width, screen_height]: [f32; 2],
-) -> Result<Vec<Vertex>, Error>
+) -> Result<Vec<TextVertex>, Error>
 where
     I: IntoIterator<Item = &'a Section>,
 {
+    let dims = cache.image().dimensions();
+    let texel = [1.0 / dims.width() as f32, 1.0 / dims.height() as f32];
+
+    let sections = sections
+        .into_iter()
+        .map(|section| {
+            let glyphs = &glyphs[section.range.clone()];
+            Ok((
+                section,
+                glyphs,
+                shapes(section, glyphs, fonts, cache, texel)?,
+            ))
+        })
+        .collect::<Result<Vec<_>, Error>>()?;
     let mut vertices = Vec::new();
-    for section in sections {
-        for gly in &glyphs[section.range.clone()] {
-            if let Some((uv_rect, screen_rect)) = cache.rect_for(section.font, &gly)? {
-                vertices.push(Vertex {
-                    tl: [
-                        to_ndc(screen_rect.min.x, screen_width),
-                        to_ndc(screen_rect.min.y, screen_height),
-                    ],
-                    br: [
-                        to_ndc(screen_rect.max.x, screen_width),
-                        to_ndc(screen_rect.max.y, screen_height),
-                    ],
-                    tex_tl: [uv_rect.min.x, uv_rect.min.y],
-                    tex_br: [uv_rect.max.x, uv_rect.max.y],
-                    color: section.color,
+
+    // Highlights and shadows are emitted first so they are drawn underneath all text in the batch.
+    for (section, glyphs, _) in &sections {
+        for (range, color) in &section.highlights {
+            for (rect, baseline) in highlight_rects(glyphs, range) {
+                let shape = rect_shape(rect, baseline, texel);
+                let (tl, br) = quad(&shape, section.y_up, [0.0, 0.0], 0.0);
+                vertices.push(TextVertex {
+                    tl,
+                    br,
+                    tex_tl: [shape.uv.min.x, shape.uv.min.y],
+                    tex_br: [shape.uv.max.x, shape.uv.max.y],
+                    kind: shape.kind,
+                    color: *color,
+                    outline_color: [0.0; 4],
+                    outline_width: 0.0,
+                    blur: 0.0,
+                    gradient_kind: GRADIENT_NONE,
+                    gradient: [0.0; 4],
+                    gradient_color: [0.0; 4],
+                    params: section.params,
+                    rotated: shape.rotated as u32,
+                });
+            }
+        }
+    }
+
+    for (section, _, shapes) in &sections {
+        if let Some(shadow) = &section.shadow {
+            let factor = section.hidpi_factor;
+            let offset = [shadow.offset[0] * factor, shadow.offset[1] * factor];
+            let blur = shadow.blur * factor;
+            for shape in shapes {
+                let (tl, br) = quad(shape, section.y_up, offset, blur.ceil());
+                vertices.push(TextVertex {
+                    tl,
+                    br,
+                    tex_tl: [shape.uv.min.x, shape.uv.min.y],
+                    tex_br: [shape.uv.max.x, shape.uv.max.y],
+                    kind: shape.kind,
+                    color: shadow.color,
+                    outline_color: [0.0; 4],
+                    outline_width: 0.0,
+                    blur,
+                    gradient_kind: GRADIENT_NONE,
+                    gradient: [0.0; 4],
+                    gradient_color: [0.0; 4],
+                    params: section.params,
+                    rotated: shape.rotated as u32,
                 });
             }
         }
     }
+
+    for (section, glyphs, shapes) in &sections {
+        let section_fill = fill(section, glyphs);
+        let outline_width = section.outline_width * section.hidpi_factor;
+        for shape in shapes {
+            let (color, gradient_kind, gradient, gradient_color) = match shape.color {
+                Some(color) => (color, GRADIENT_NONE, [0.0; 4], [0.0; 4]),
+                None => section_fill,
+            };
+            let (tl, br) = quad(shape, section.y_up, [0.0, 0.0], outline_width.ceil());
+            vertices.push(TextVertex {
+                tl,
+                br,
+                tex_tl: [shape.uv.min.x, shape.uv.min.y],
+                tex_br: [shape.uv.max.x, shape.uv.max.y],
+                kind: shape.kind,
+                color,
+                outline_color: section.outline_color,
+                outline_width,
+                blur: 0.0,
+                gradient_kind,
+                gradient,
+                gradient_color,
+                params: section.params,
+                rotated: shape.rotated as u32,
+            });
+        }
+    }
     Ok(vertices)
 }
 
-fn to_ndc(x: i32, size: f32) -> f32 {
-    (2 * x) as f32 / size - 1.0
+/// A quad to be drawn for a section.
+struct Shape {
+    kind: u32,
+    /// The position of the quad, in pixels.
+    rect: Rect<f32>,
+    /// The texture coordinates of the quad. For solid rectangles this does not refer to the
+    /// cache, but is sized so that one pixel still corresponds to one texel.
+    uv: Rect<f32>,
+    /// The colour of the quad, if it overrides the fill of the section.
+    color: Option<[f32; 4]>,
+    /// The baseline the quad is reflected around when y points up.
+    baseline: f32,
+    /// Whether the glyph is rotated a quarter turn clockwise within the quad.
+    rotated: bool,
+}
+
+/// Get the glyphs and decorations of a section, in drawing order.
+fn shapes<'font>(
+    section: &Section,
+    glyphs: &[QueuedGlyph<'font>],
+    fonts: &[RegisteredFont<'font>],
+    cache: &GpuCache<'font>,
+    texel: [f32; 2],
+) -> Result<Vec<Shape>, Error> {
+    let mut shapes: Vec<Shape> = decoration_rects(section.decorations, glyphs, fonts)
+        .into_iter()
+        .map(|(rect, baseline, color)| Shape {
+            color,
+            ..rect_shape(rect, baseline, texel)
+        })
+        .collect();
+
+    for gly in glyphs {
+        if let Some((uv, screen_rect)) = cache.rect_for(gly.font.index, &gly.glyph)? {
+            let mut rect = Rect {
+                min: point(screen_rect.min.x as f32, screen_rect.min.y as f32),
+                max: point(screen_rect.max.x as f32, screen_rect.max.y as f32),
+            };
+            let origin = gly.glyph.position();
+            if gly.rotated {
+                // Turning clockwise around the origin maps (x, y) to (-y, x) relative to it.
+                rect = Rect {
+                    min: point(
+                        origin.x + origin.y - rect.max.y,
+                        origin.y + rect.min.x - origin.x,
+                    ),
+                    max: point(
+                        origin.x + origin.y - rect.min.y,
+                        origin.y + rect.max.x - origin.x,
+                    ),
+                };
+            }
+            shapes.push(Shape {
+                kind: KIND_GLYPH,
+                rect,
+                uv,
+                color: gly.color,
+                baseline: origin.y,
+                rotated: gly.rotated,
+            });
+        }
+    }
+    Ok(shapes)
+}
+
+fn rect_shape(rect: Rect<f32>, baseline: f32, [texel_w, texel_h]: [f32; 2]) -> Shape {
+    Shape {
+        kind: KIND_RECT,
+        uv: Rect {
+            min: point(0.0, 0.0),
+            max: point(rect.width() * texel_w, rect.height() * texel_h),
+        },
+        rect,
+        color: None,
+        baseline,
+        rotated: false,
+    }
+}
+
+/// Get the corners of a quad in pixels. The quad is grown by `pad` pixels on each side to leave
+/// room for outlines and blur; the vertex shader extends the texture coordinates by the same
+/// amount.
+///
+/// Glyph metrics always have y pointing down, so if `y_up` is set the quad is reflected around
+/// its baseline before being offset. The "top left" corner then has the larger y coordinate,
+/// which keeps the texture upright.
+fn quad(shape: &Shape, y_up: bool, [dx, dy]: [f32; 2], pad: f32) -> ([f32; 2], [f32; 2]) {
+    let rect = shape.rect;
+    let (mut top, mut bottom) = (rect.min.y - pad, rect.max.y + pad);
+    if y_up {
+        top = 2.0 * shape.baseline - top;
+        bottom = 2.0 * shape.baseline - bottom;
+    }
+    let tl = [rect.min.x + dx - pad, top + dy];
+    let br = [rect.max.x + dx + pad, bottom + dy];
+    (tl, br)
+}
+
+/// Get the fill parameters for a section, with any gradient converted to pixel coordinates.
+fn fill(section: &Section, glyphs: &[QueuedGlyph<'_>]) -> ([f32; 4], u32, [f32; 4], [f32; 4]) {
+    let (gradient, space) = match section.gradient {
+        Some(gradient) => gradient,
+        None => return (section.color, GRADIENT_NONE, [0.0; 4], [0.0; 4]),
+    };
+
+    // Map section space onto the bounding box of the glyphs.
+    let ([x, y], [width, height]) = match space {
+        GradientSpace::Screen => ([0.0, 0.0], [section.hidpi_factor, section.hidpi_factor]),
+        GradientSpace::Section => match bounding_box(glyphs, section.y_up) {
+            // Section space always has its origin at the top, so flip it when y points up.
+            Some(rect) if section.y_up => {
+                ([rect.min.x, rect.max.y], [rect.width(), -rect.height()])
+            }
+            Some(rect) => ([rect.min.x, rect.min.y], [rect.width(), rect.height()]),
+            None => ([0.0, 0.0], [1.0, 1.0]),
+        },
+    };
+
+    match gradient {
+        Gradient::Linear {
+            start,
+            start_color,
+            end,
+            end_color,
+        } => (
+            start_color,
+            GRADIENT_LINEAR,
+            [
+                x + start[0] * width,
+                y + start[1] * height,
+                x + end[0] * width,
+                y + end[1] * height,
+            ],
+            end_color,
+        ),
+        Gradient::Radial {
+            center,
+            center_color,
+            radius,
+            edge_color,
+        } => (
+            center_color,
+            GRADIENT_RADIAL,
+            [
+                x + center[0] * width,
+                y + center[1] * height,
+                radius * width,
+                radius * height.abs(),
+            ],
+            edge_color,
+        ),
+    }
+}
+
+/// Get the bounding box of some glyphs in pixels, reflecting each glyph around its baseline if
+/// `y_up` is set.
+fn bounding_box(glyphs: &[QueuedGlyph<'_>], y_up: bool) -> Option<Rect<f32>> {
+    glyphs
+        .iter()
+        .filter_map(|gly| {
+            let rect = gly.glyph.pixel_bounding_box()?;
+            let (left, right) = (rect.min.x as f32, rect.max.x as f32);
+            let (top, bottom) = (rect.min.y as f32, rect.max.y as f32);
+            let (min_y, max_y) = if y_up {
+                let baseline = gly.glyph.position().y;
+                (2.0 * baseline - bottom, 2.0 * baseline - top)
+            } else {
+                (top, bottom)
+            };
+            Some(Rect {
+                min: point(left, min_y),
+                max: point(right, max_y),
+            })
+        })
+        .fold(None, |acc, rect| match acc {
+            None => Some(rect),
+            Some(acc) => Some(Rect {
+                min: point(acc.min.x.min(rect.min.x), acc.min.y.min(rect.min.y)),
+                max: point(acc.max.x.max(rect.max.x), acc.max.y.max(rect.max.y)),
+            }),
+        })
+}
+
+/// Combine the user's transform with the conversion from pixels to normalized device coordinates
+/// within the viewport.
+fn pixel_transform(
+    transform: [[f32; 4]; 4],
+    viewport: &Viewport,
+    coordinates: Coordinates,
+) -> [[f32; 4]; 4] {
+    let [x, y] = viewport.origin;
+    let [width, height] = viewport.dimensions;
+    let to_ndc = match coordinates {
+        Coordinates::TopLeft => [
+            [2.0 / width, 0.0, 0.0, 0.0],
+            [0.0, 2.0 / height, 0.0, 0.0],
+            [0.0, 0.0, 1.0, 0.0],
+            [-2.0 * x / width - 1.0, -2.0 * y / height - 1.0, 0.0, 1.0],
+        ],
+        Coordinates::BottomLeft => [
+            [2.0 / width, 0.0, 0.0, 0.0],
+            [0.0, -2.0 / height, 0.0, 0.0],
+            [0.0, 0.0, 1.0, 0.0],
+            [-2.0 * x / width - 1.0, 2.0 * y / height + 1.0, 0.0, 1.0],
+        ],
+        Coordinates::Raw { .. } => return transform,
+    };
+    mul(transform, to_ndc)
+}
+
+/// Multiply two column-major matrices.
+fn mul(a: [[f32; 4]; 4], b: [[f32; 4]; 4]) -> [[f32; 4]; 4] {
+    let mut result = [[0.0; 4]; 4];
+    for (col, b_col) in result.iter_mut().zip(b.iter()) {
+        for (row, out) in col.iter_mut().enumerate() {
+            *out = (0..4).map(|k| a[k][row] * b_col[k]).sum();
+        }
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use rusttype::{Font, Scale};
+
+    use super::*;
+    use crate::FontId;
+
+    static MONO: &[u8] = include_bytes!("../tests/fonts/DejaVuSansMono.ttf");
+
+    const IDENTITY: [[f32; 4]; 4] = [
+        [1.0, 0.0, 0.0, 0.0],
+        [0.0, 1.0, 0.0, 0.0],
+        [0.0, 0.0, 1.0, 0.0],
+        [0.0, 0.0, 0.0, 1.0],
+    ];
+
+    fn viewport() -> Viewport {
+        Viewport {
+            origin: [10.0, 20.0],
+            dimensions: [200.0, 100.0],
+            depth_range: 0.0..1.0,
+        }
+    }
+
+    /// Apply a column-major transform to a point.
+    fn apply(m: [[f32; 4]; 4], [x, y]: [f32; 2]) -> [f32; 2] {
+        let v = [x, y, 0.0, 1.0];
+        let row = |r: usize| (0..4).map(|k| m[k][r] * v[k]).sum();
+        [row(0), row(1)]
+    }
+
+    fn assert_close(left: [f32; 2], right: [f32; 2]) {
+        assert!(
+            (left[0] - right[0]).abs() < 1e-5 && (left[1] - right[1]).abs() < 1e-5,
+            "{:?} != {:?}",
+            left,
+            right
+        );
+    }
+
+    fn shape(rect: Rect<f32>, baseline: f32) -> Shape {
+        Shape {
+            kind: KIND_GLYPH,
+            rect,
+            uv: Rect {
+                min: point(0.0, 0.0),
+                max: point(1.0, 1.0),
+            },
+            color: None,
+            baseline,
+            rotated: false,
+        }
+    }
+
+    #[test]
+    fn top_left_coordinates_map_the_viewport_corners() {
+        let m = pixel_transform(IDENTITY, &viewport(), Coordinates::TopLeft);
+        assert_close(apply(m, [10.0, 20.0]), [-1.0, -1.0]);
+        assert_close(apply(m, [210.0, 20.0]), [1.0, -1.0]);
+        assert_close(apply(m, [10.0, 120.0]), [-1.0, 1.0]);
+        assert_close(apply(m, [210.0, 120.0]), [1.0, 1.0]);
+    }
+
+    #[test]
+    fn bottom_left_coordinates_map_the_viewport_corners() {
+        // Normalized device coordinates point down, so the bottom of the viewport is at +1.
+        let m = pixel_transform(IDENTITY, &viewport(), Coordinates::BottomLeft);
+        assert_close(apply(m, [10.0, 20.0]), [-1.0, 1.0]);
+        assert_close(apply(m, [210.0, 20.0]), [1.0, 1.0]);
+        assert_close(apply(m, [10.0, 120.0]), [-1.0, -1.0]);
+        assert_close(apply(m, [210.0, 120.0]), [1.0, -1.0]);
+    }
+
+    #[test]
+    fn the_transform_is_applied_after_the_viewport() {
+        let mut half = IDENTITY;
+        half[0][0] = 0.5;
+        half[1][1] = 0.5;
+        let m = pixel_transform(half, &viewport(), Coordinates::TopLeft);
+        assert_close(apply(m, [10.0, 20.0]), [-0.5, -0.5]);
+        assert_close(apply(m, [210.0, 120.0]), [0.5, 0.5]);
+
+        let raw = Coordinates::Raw { y_up: false };
+        assert_eq!(pixel_transform(half, &viewport(), raw), half);
+    }
+
+    #[test]
+    fn quads_are_reflected_around_the_baseline_when_y_up() {
+        let rect = Rect {
+            min: point(4.0, 20.0),
+            max: point(12.0, 32.0),
+        };
+        let shape = shape(rect, 30.0);
+        assert_eq!(
+            quad(&shape, false, [1.0, 2.0], 1.0),
+            ([4.0, 21.0], [14.0, 35.0])
+        );
+        // The top edge at 19 is reflected to 41 and the bottom edge at 33 to 27.
+        assert_eq!(
+            quad(&shape, true, [1.0, 2.0], 1.0),
+            ([4.0, 43.0], [14.0, 29.0])
+        );
+    }
+
+    #[test]
+    fn fill_without_a_gradient_uses_the_section_color() {
+        let section = Section::new(0..0, [0.1, 0.2, 0.3, 0.4], 1.0, false);
+        assert_eq!(
+            fill(&section, &[]),
+            ([0.1, 0.2, 0.3, 0.4], GRADIENT_NONE, [0.0; 4], [0.0; 4])
+        );
+    }
+
+    #[test]
+    fn section_gradients_start_at_the_top_when_y_up() {
+        let font = Font::from_bytes(MONO).unwrap();
+        let glyph = font
+            .glyph('a')
+            .scaled(Scale::uniform(20.0))
+            .positioned(point(10.0, 30.0));
+        let pixels = glyph.pixel_bounding_box().unwrap();
+        let glyphs = [QueuedGlyph::new(glyph, FontId { brush: 0, index: 0 }, 0..1)];
+        let gradient = Gradient::Linear {
+            start: [0.0, 0.0],
+            start_color: [1.0; 4],
+            end: [1.0, 1.0],
+            end_color: [0.0; 4],
+        };
+
+        let section = Section::new(0..1, [1.0; 4], 1.0, false)
+            .with_gradient(gradient, GradientSpace::Section);
+        let (_, kind, points, _) = fill(&section, &glyphs);
+        assert_eq!(kind, GRADIENT_LINEAR);
+        let (left, right) = (pixels.min.x as f32, pixels.max.x as f32);
+        let (top, bottom) = (pixels.min.y as f32, pixels.max.y as f32);
+        assert_eq!(points, [left, top, right, bottom]);
+
+        // With y pointing up, the glyph is reflected around its baseline, and the start of the
+        // gradient stays at the top of the glyph on screen.
+        let section =
+            Section::new(0..1, [1.0; 4], 1.0, true).with_gradient(gradient, GradientSpace::Section);
+        let (_, _, points, _) = fill(&section, &glyphs);
+        assert_eq!(points, [left, 60.0 - top, right, 60.0 - bottom]);
+    }
+
+    #[test]
+    fn screen_gradients_are_scaled_by_the_hidpi_factor() {
+        let gradient = Gradient::Radial {
+            center: [10.0, 20.0],
+            center_color: [1.0; 4],
+            radius: 5.0,
+            edge_color: [0.0; 4],
+        };
+        let section =
+            Section::new(0..0, [1.0; 4], 2.0, true).with_gradient(gradient, GradientSpace::Screen);
+        let (_, kind, params, _) = fill(&section, &[]);
+        assert_eq!(kind, GRADIENT_RADIAL);
+        assert_eq!(params, [20.0, 40.0, 10.0, 10.0]);
+    }
 }
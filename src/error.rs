@@ -2,11 +2,13 @@ use std::{error, fmt, result};
 
 use rusttype::gpu_cache::CacheReadErr;
 use vulkano::command_buffer::{
-    BuildError, CommandBufferExecError, CopyBufferImageError, DrawIndirectError,
+    AutoCommandBufferBuilderContextError, BeginRenderPassError, BuildError, CommandBufferExecError,
+    CopyBufferImageError, DrawIndirectError,
 };
 use vulkano::descriptor::descriptor_set::{
     PersistentDescriptorSetBuildError, PersistentDescriptorSetError,
 };
+use vulkano::framebuffer::{FramebufferCreationError, RenderPassCreationError};
 use vulkano::image::ImageCreationError;
 use vulkano::memory::DeviceMemoryAllocError;
 use vulkano::pipeline::GraphicsPipelineCreationError;
@@ -39,6 +41,8 @@ impl Error {
 pub enum ErrorKind {
     /// A requested glyph was not in the cache.
     CacheRead(CacheReadErr),
+    /// Font data could not be loaded.
+    Font(rusttype::Error),
     Build(BuildError),
     CopyBufferImage(CopyBufferImageError),
     CommandBufferExec(CommandBufferExecError),
@@ -49,11 +53,27 @@ pub enum ErrorKind {
     GraphicsPipelineCreation(GraphicsPipelineCreationError),
     PersistentDescriptorSet(PersistentDescriptorSetError),
     PersistentDescriptorSetBuild(PersistentDescriptorSetBuildError),
+    RenderPassCreation(RenderPassCreationError),
+    FramebufferCreation(FramebufferCreationError),
+    BeginRenderPass(BeginRenderPassError),
+    AutoCommandBufferBuilderContext(AutoCommandBufferBuilderContextError),
     Oom(OomError),
+    /// A `TextBuffer` was drawn without being included in the latest call to
+    /// `GlyphBrush::cache_text_buffers`, or after a call to `GlyphBrush::cache_sections`. Any
+    /// call to either function may evict the glyphs of buffers it was not given.
+    OutdatedTextBuffer,
+    /// Text was drawn with a `DynamicState` that has no viewport.
+    MissingViewport,
     #[doc(hidden)]
     __NonExhaustive,
 }
 
+impl From<ErrorKind> for Error {
+    fn from(kind: ErrorKind) -> Self {
+        Error::new(kind)
+    }
+}
+
 impl From<CacheReadErr> for Error {
     fn from(err: CacheReadErr) -> Self {
         Error::new(ErrorKind::CacheRead(err))
@@ -84,6 +104,12 @@ impl From<DeviceMemoryAllocError> for Error {
     }
 }
 
+impl From<rusttype::Error> for Error {
+    fn from(err: rusttype::Error) -> Self {
+        Error::new(ErrorKind::Font(err))
+    }
+}
+
 impl From<OomError> for Error {
     fn from(err: OomError) -> Self {
         Error::new(ErrorKind::Oom(err))
@@ -126,10 +152,35 @@ impl From<PersistentDescriptorSetBuildError> for Error {
     }
 }
 
+impl From<RenderPassCreationError> for Error {
+    fn from(err: RenderPassCreationError) -> Self {
+        Error::new(ErrorKind::RenderPassCreation(err))
+    }
+}
+
+impl From<FramebufferCreationError> for Error {
+    fn from(err: FramebufferCreationError) -> Self {
+        Error::new(ErrorKind::FramebufferCreation(err))
+    }
+}
+
+impl From<BeginRenderPassError> for Error {
+    fn from(err: BeginRenderPassError) -> Self {
+        Error::new(ErrorKind::BeginRenderPass(err))
+    }
+}
+
+impl From<AutoCommandBufferBuilderContextError> for Error {
+    fn from(err: AutoCommandBufferBuilderContextError) -> Self {
+        Error::new(ErrorKind::AutoCommandBufferBuilderContext(err))
+    }
+}
+
 impl fmt::Display for Error {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self.kind() {
             ErrorKind::CacheRead(err) => err.fmt(f),
+            ErrorKind::Font(err) => err.fmt(f),
             ErrorKind::CopyBufferImage(err) => err.fmt(f),
             ErrorKind::Build(err) => err.fmt(f),
             ErrorKind::CommandBufferExec(err) => err.fmt(f),
@@ -141,6 +192,17 @@ impl fmt::Display for Error {
             ErrorKind::Oom(err) => err.fmt(f),
             ErrorKind::PersistentDescriptorSet(err) => err.fmt(f),
             ErrorKind::PersistentDescriptorSetBuild(err) => err.fmt(f),
+            ErrorKind::RenderPassCreation(err) => err.fmt(f),
+            ErrorKind::FramebufferCreation(err) => err.fmt(f),
+            ErrorKind::BeginRenderPass(err) => err.fmt(f),
+            ErrorKind::AutoCommandBufferBuilderContext(err) => err.fmt(f),
+            ErrorKind::OutdatedTextBuffer => {
+                write!(
+                    f,
+                    "the text buffer was not included in the latest cache update"
+                )
+            }
+            ErrorKind::MissingViewport => write!(f, "no viewport was provided"),
             ErrorKind::__NonExhaustive => unreachable!(),
         }
     }
@@ -149,7 +211,9 @@ impl fmt::Display for Error {
 impl error::Error for Error {
     fn cause(&self) -> Option<&dyn error::Error> {
         Some(match self.kind() {
+            ErrorKind::OutdatedTextBuffer | ErrorKind::MissingViewport => return None,
             ErrorKind::CacheRead(err) => err,
+            ErrorKind::Font(err) => err,
             ErrorKind::CopyBufferImage(err) => err,
             ErrorKind::Build(err) => err,
             ErrorKind::CommandBufferExec(err) => err,
@@ -161,6 +225,10 @@ impl error::Error for Error {
             ErrorKind::Oom(err) => err,
             ErrorKind::PersistentDescriptorSet(err) => err,
             ErrorKind::PersistentDescriptorSetBuild(err) => err,
+            ErrorKind::RenderPassCreation(err) => err,
+            ErrorKind::FramebufferCreation(err) => err,
+            ErrorKind::BeginRenderPass(err) => err,
+            ErrorKind::AutoCommandBufferBuilderContext(err) => err,
             ErrorKind::__NonExhaustive => unreachable!(),
         })
     }
@@ -0,0 +1,1207 @@
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::f32;
+use std::mem;
+use std::ops::Range;
+
+use rusttype::{point, Font, GlyphId, PositionedGlyph, Scale, ScaledGlyph, VMetrics};
+use unicode_bidi::BidiInfo;
+use unicode_bidi_mirroring::get_mirrored;
+
+use crate::section::QueuedGlyph;
+#[cfg(feature = "shaping")]
+use crate::shaping::{shape, Shaping};
+use crate::{Decorations, FontId};
+
+/// Options for laying out text with `GlyphBrush::queue_text` or `GlyphBrush::queue_spans`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Layout {
+    pub(crate) scale: Scale,
+    pub(crate) position: [f32; 2],
+    pub(crate) max_width: Option<f32>,
+    pub(crate) max_height: Option<f32>,
+    pub(crate) h_align: HAlign,
+    pub(crate) v_align: VAlign,
+    pub(crate) kerning: bool,
+    pub(crate) writing_mode: WritingMode,
+    #[cfg(feature = "shaping")]
+    pub(crate) shaping: Option<Shaping>,
+}
+
+/// How lines are placed horizontally relative to the position of a `Layout`.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum HAlign {
+    /// Lines start at the position. This is the default.
+    Left,
+    /// Lines are centred on the position.
+    Center,
+    /// Lines end at the position.
+    Right,
+    /// Lines start at the position, and the space between words is stretched so that they fill
+    /// the maximum width. The last line of each paragraph is left aligned, as are all lines if
+    /// there is no maximum width.
+    Justify,
+}
+
+impl Default for HAlign {
+    fn default() -> Self {
+        HAlign::Left
+    }
+}
+
+/// How the block of lines is placed vertically relative to the position of a `Layout`.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum VAlign {
+    /// The top of the first line is at the position. This is the default.
+    Top,
+    /// The text is centred on the position, from the top of the first line to the bottom of the
+    /// last.
+    Middle,
+    /// The baseline of the first line is at the position.
+    Baseline,
+    /// The bottom of the last line is at the position.
+    Bottom,
+}
+
+impl Default for VAlign {
+    fn default() -> Self {
+        VAlign::Top
+    }
+}
+
+/// The direction in which lines of text run and are stacked.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum WritingMode {
+    /// Lines run from left to right and are stacked from top to bottom, with right-to-left
+    /// scripts reordered within each line. This is the default.
+    Horizontal,
+    /// Columns run from top to bottom and are stacked from right to left, as in Japanese and
+    /// Chinese. CJK characters are drawn upright, and other characters are rotated clockwise.
+    VerticalRl,
+}
+
+impl Default for WritingMode {
+    fn default() -> Self {
+        WritingMode::Horizontal
+    }
+}
+
+impl Layout {
+    /// Lay out text at the given scale, with the top left corner of the first line at `position`.
+    /// The position is measured in logical pixels.
+    pub fn new(scale: Scale, position: [f32; 2]) -> Self {
+        Layout {
+            scale,
+            position,
+            max_width: None,
+            max_height: None,
+            h_align: HAlign::default(),
+            v_align: VAlign::default(),
+            kerning: true,
+            writing_mode: WritingMode::default(),
+            #[cfg(feature = "shaping")]
+            shaping: None,
+        }
+    }
+
+    /// Wrap lines between words so that they are no wider than `max_width` logical pixels. Words
+    /// which do not fit on a line by themselves are broken between characters. This only applies
+    /// to horizontal text.
+    pub fn with_max_width(mut self, max_width: f32) -> Self {
+        self.max_width = Some(max_width);
+        self
+    }
+
+    /// Break columns of vertical text between characters so that they are no taller than
+    /// `max_height` logical pixels. This only applies to vertical text.
+    pub fn with_max_height(mut self, max_height: f32) -> Self {
+        self.max_height = Some(max_height);
+        self
+    }
+
+    /// Align lines horizontally relative to the position.
+    pub fn with_h_align(mut self, h_align: HAlign) -> Self {
+        self.h_align = h_align;
+        self
+    }
+
+    /// Align the text vertically relative to the position.
+    pub fn with_v_align(mut self, v_align: VAlign) -> Self {
+        self.v_align = v_align;
+        self
+    }
+
+    /// Whether to adjust the spacing between pairs of glyphs using the kerning table of the
+    /// font. This is enabled by default.
+    pub fn with_kerning(mut self, kerning: bool) -> Self {
+        self.kerning = kerning;
+        self
+    }
+
+    /// Set the direction in which text is written.
+    ///
+    /// In vertical writing modes the position is the top right corner of the first column, and
+    /// columns are broken at the maximum height set with `Layout::with_max_height`. Alignment,
+    /// kerning and shaping only apply to horizontal text. Decorations and highlights are not
+    /// drawn for vertical text, so that they are not broken into a separate line for every glyph
+    /// of a column.
+    ///
+    /// Upright glyphs advance by the vertical metrics in the `vmtx` table of their font, which
+    /// is only read for fonts added with `GlyphBrush::add_font_bytes`. For other fonts, and for
+    /// fonts without the table, every upright glyph is approximated as being as tall as the font,
+    /// from its ascent to its descent, which spaces glyphs with a different vertical advance
+    /// unevenly.
+    pub fn with_writing_mode(mut self, writing_mode: WritingMode) -> Self {
+        self.writing_mode = writing_mode;
+        self
+    }
+
+    /// Shape text using the OpenType layout tables of the font, which is needed for scripts such
+    /// as Arabic and Devanagari and for ligatures. Shaping replaces pair kerning, and only
+    /// applies to fonts added with `GlyphBrush::add_font_bytes`.
+    #[cfg(feature = "shaping")]
+    pub fn with_shaping(mut self, shaping: Shaping) -> Self {
+        self.shaping = Some(shaping);
+        self
+    }
+}
+
+/// A run of text with its own font and style, for `GlyphBrush::queue_spans`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Span<'a> {
+    pub(crate) text: Cow<'a, str>,
+    pub(crate) font: FontId,
+    pub(crate) scale: Option<Scale>,
+    pub(crate) color: Option<[f32; 4]>,
+    pub(crate) decorations: Option<Decorations>,
+}
+
+impl<'a> Span<'a> {
+    /// A span of text drawn with the given font, at the scale of the layout and with the fill and
+    /// decorations of the section.
+    pub fn new<T>(text: T, font: FontId) -> Self
+    where
+        T: Into<Cow<'a, str>>,
+    {
+        Span {
+            text: text.into(),
+            font,
+            scale: None,
+            color: None,
+            decorations: None,
+        }
+    }
+
+    /// Draw the span at a different scale from the layout. Each line is spaced to fit the
+    /// largest font on it.
+    pub fn with_scale(mut self, scale: Scale) -> Self {
+        self.scale = Some(scale);
+        self
+    }
+
+    /// Draw the span in its own colour, instead of the fill of the section.
+    pub fn with_color(mut self, color: [f32; 4]) -> Self {
+        self.color = Some(color);
+        self
+    }
+
+    /// Draw lines such as underlines across the span, instead of those set with
+    /// `Section::with_decorations`.
+    pub fn with_decorations(mut self, decorations: Decorations) -> Self {
+        self.decorations = Some(decorations);
+        self
+    }
+}
+
+/// A font which glyphs can be taken from during layout.
+#[derive(Copy, Clone)]
+pub(crate) struct LayoutFont<'a, 'font> {
+    pub(crate) id: FontId,
+    pub(crate) font: &'a Font<'font>,
+    /// The raw font data, if available, which is needed for shaping and vertical metrics.
+    pub(crate) data: Option<&'a [u8]>,
+}
+
+/// The style of a run of text during layout.
+pub(crate) struct Style<'a, 'font> {
+    /// The fonts which glyphs are taken from, in order of preference.
+    pub(crate) fonts: Vec<LayoutFont<'a, 'font>>,
+    pub(crate) scale: Scale,
+    pub(crate) color: Option<[f32; 4]>,
+    pub(crate) decorations: Option<Decorations>,
+    /// The vertical metrics of the first font at the scale.
+    pub(crate) v_metrics: VMetrics,
+}
+
+impl<'a, 'font> Style<'a, 'font> {
+    pub(crate) fn new(fonts: Vec<LayoutFont<'a, 'font>>, scale: Scale) -> Self {
+        Style {
+            v_metrics: fonts[0].font.v_metrics(scale),
+            fonts,
+            scale,
+            color: None,
+            decorations: None,
+        }
+    }
+
+    /// Queue a glyph with the colour and decorations of this style.
+    fn queued(
+        &self,
+        glyph: PositionedGlyph<'font>,
+        font: FontId,
+        source: Range<usize>,
+    ) -> QueuedGlyph<'font> {
+        QueuedGlyph {
+            color: self.color,
+            decorations: self.decorations,
+            ..QueuedGlyph::new(glyph, font, source)
+        }
+    }
+}
+
+/// A line of glyphs, before it is aligned.
+#[derive(Default)]
+struct Line<'font> {
+    /// The glyphs of the line, in logical order.
+    glyphs: Vec<LineGlyph<'font>>,
+    /// The width of the line, excluding any trailing whitespace.
+    width: f32,
+    /// The number of words on the line.
+    words: usize,
+    /// The largest ascent of the styles on the line.
+    ascent: f32,
+    /// The lowest descent of the styles on the line.
+    descent: f32,
+    /// The largest line gap of the styles on the line.
+    line_gap: f32,
+    /// The embedding level of the paragraph containing the line.
+    level: u8,
+    /// Whether this is the last line of a paragraph.
+    last: bool,
+}
+
+/// A glyph within a line, before it is positioned.
+struct LineGlyph<'font> {
+    glyph: ScaledGlyph<'font>,
+    font: FontId,
+    /// The index of the style of the glyph.
+    style: usize,
+    /// The kerning between this glyph and the previous one, if it is on the same line.
+    kern: f32,
+    advance: f32,
+    /// The offset of the glyph from its pen position, from shaping.
+    offset: [f32; 2],
+    /// The range of the text the glyph was laid out from. Every glyph of a ligature or cluster
+    /// has the range of the whole cluster.
+    source: Range<usize>,
+    /// The bidirectional embedding level of the glyph. Odd levels are right-to-left.
+    level: u8,
+    /// The index of the word containing the glyph within the line.
+    word: usize,
+    whitespace: bool,
+}
+
+/// Lay out a string into aligned lines, each spaced to fit the first font of every style on it.
+/// `style_indices` holds the index into `styles` of each byte of the string, and each character
+/// is taken from the first font of its style which contains it. If `y_up` is set, the y axis
+/// points up and lines are stacked towards negative y.
+pub(crate) fn layout_glyphs<'font>(
+    text: &str,
+    styles: &[Style<'_, 'font>],
+    style_indices: &[usize],
+    layout: &Layout,
+    y_up: bool,
+) -> Vec<QueuedGlyph<'font>> {
+    let down = if y_up { -1.0 } else { 1.0 };
+    if layout.writing_mode == WritingMode::VerticalRl {
+        return layout_vertical(text, styles, style_indices, layout, down);
+    }
+
+    let [x, y] = layout.position;
+    let lines: Vec<Line<'font>> = paragraphs(text)
+        .into_iter()
+        .flat_map(|(start, paragraph)| {
+            let indices = &style_indices[start..start + paragraph.len()];
+            let v_metrics = styles[style_indices[start]].v_metrics;
+            wrap(paragraph, start, styles, indices, v_metrics, layout)
+        })
+        .collect();
+
+    let height: f32 = lines
+        .iter()
+        .enumerate()
+        .map(|(i, line)| {
+            let gap = if i + 1 < lines.len() {
+                line.line_gap
+            } else {
+                0.0
+            };
+            line.ascent - line.descent + gap
+        })
+        .sum();
+    let offset = match layout.v_align {
+        VAlign::Top => 0.0,
+        VAlign::Middle => height / 2.0,
+        VAlign::Baseline => lines.first().map_or(0.0, |line| line.ascent),
+        VAlign::Bottom => height,
+    };
+    let top = y - down * offset;
+
+    let mut glyphs = Vec::new();
+    let mut line_top = top;
+    for line in lines {
+        let baseline = line_top + down * line.ascent;
+        line_top = baseline + down * (line.line_gap - line.descent);
+        let (left, gap) = match layout.h_align {
+            HAlign::Left => (x, 0.0),
+            HAlign::Center => (x - line.width / 2.0, 0.0),
+            HAlign::Right => (x - line.width, 0.0),
+            HAlign::Justify => match layout.max_width {
+                Some(max_width) if !line.last && line.words > 1 => {
+                    (x, (max_width - line.width) / (line.words - 1) as f32)
+                }
+                _ => (x, 0.0),
+            },
+        };
+        place(line, styles, left, baseline, down, gap, &mut glyphs);
+    }
+    glyphs
+}
+
+/// Position the glyphs of a line in visual order, starting at `left` on the baseline and adding
+/// `gap` between words. `down` is the sign of the downward direction of the y axis.
+fn place<'font>(
+    line: Line<'font>,
+    styles: &[Style<'_, 'font>],
+    left: f32,
+    baseline: f32,
+    down: f32,
+    gap: f32,
+    out: &mut Vec<QueuedGlyph<'font>>,
+) {
+    // Trailing whitespace takes no space, and is moved to the paragraph level as in rule L1 of
+    // the bidirectional algorithm.
+    let trailing = line
+        .glyphs
+        .iter()
+        .rposition(|gly| !gly.whitespace)
+        .map_or(0, |i| i + 1);
+    let levels: Vec<u8> = line
+        .glyphs
+        .iter()
+        .enumerate()
+        .map(|(i, gly)| if i < trailing { gly.level } else { line.level })
+        .collect();
+
+    let mut glyphs: Vec<_> = line.glyphs.into_iter().enumerate().collect();
+    let order = visual_order(&levels);
+    glyphs.sort_by_key(|&(i, _)| order[i]);
+
+    let mut x = left;
+    let mut prev_word = None;
+    for (i, gly) in glyphs {
+        let style = &styles[gly.style];
+        if i >= trailing {
+            let glyph = gly.glyph.positioned(point(x, baseline));
+            out.push(style.queued(glyph, gly.font, gly.source));
+            continue;
+        }
+        if prev_word.map_or(false, |word| word != gly.word) {
+            x += gap;
+        }
+        prev_word = Some(gly.word);
+
+        x += gly.kern;
+        let position = point(x + gly.offset[0], baseline + down * gly.offset[1]);
+        out.push(style.queued(gly.glyph.positioned(position), gly.font, gly.source));
+        x += gly.advance;
+    }
+}
+
+/// Lay out a string in columns running from top to bottom, stacked from right to left and spaced
+/// to fit the largest style. Upright glyphs advance by their vertical metrics and are centred in
+/// their column, while rotated glyphs advance by their width with their baseline centred in the
+/// column. `down` is the sign of the downward direction of the y axis.
+fn layout_vertical<'a, 'font>(
+    text: &str,
+    styles: &[Style<'a, 'font>],
+    style_indices: &[usize],
+    layout: &Layout,
+    down: f32,
+) -> Vec<QueuedGlyph<'font>> {
+    let column_width = styles
+        .iter()
+        .map(|style| {
+            let v_metrics = style.v_metrics;
+            v_metrics.ascent - v_metrics.descent + v_metrics.line_gap
+        })
+        .fold(0.0, f32::max);
+    let max_height = layout.max_height.unwrap_or(f32::INFINITY);
+    let [right, top] = layout.position;
+
+    let mut faces = HashMap::new();
+    let mut glyphs = Vec::new();
+    let mut column = 0;
+    for (start, paragraph) in paragraphs(text) {
+        let mut y = 0.0;
+        for (i, c) in paragraph.char_indices() {
+            let style = &styles[style_indices[start + i]];
+            let v_metrics = style.v_metrics;
+            let font = lookup(&style.fonts, c);
+            let glyph = font.font.glyph(c).scaled(style.scale);
+            let width = glyph.h_metrics().advance_width;
+            let upright = is_upright(c);
+            let (advance, baseline) = if upright {
+                let face = faces.entry(font.id).or_insert_with(|| {
+                    font.data
+                        .and_then(|data| ttf_parser::Face::from_slice(data, 0).ok())
+                });
+                upright_metrics(face.as_ref(), font.font, &glyph, v_metrics)
+            } else {
+                (width, 0.0)
+            };
+
+            if !c.is_whitespace() && y + advance > max_height && y > 0.0 {
+                column += 1;
+                y = 0.0;
+            }
+            let center = right - (column as f32 + 0.5) * column_width;
+            let (position, rotated) = if upright {
+                (
+                    point(center - width / 2.0, top + down * (y + baseline)),
+                    false,
+                )
+            } else {
+                let x = center - (v_metrics.ascent + v_metrics.descent) / 2.0;
+                (point(x, top + down * y), true)
+            };
+            let source = start + i..start + i + c.len_utf8();
+            glyphs.push(QueuedGlyph {
+                rotated,
+                vertical: true,
+                ..style.queued(glyph.positioned(position), font.id, source)
+            });
+            y += advance;
+        }
+        column += 1;
+    }
+    glyphs
+}
+
+/// Get the vertical advance of an upright glyph and the distance from the top of its advance to
+/// its baseline, in pixels. These come from the `vmtx` table of the font where it has one, and
+/// otherwise the glyph is given the height of the font with its ascent above the baseline.
+fn upright_metrics(
+    face: Option<&ttf_parser::Face<'_>>,
+    font: &Font<'_>,
+    glyph: &ScaledGlyph<'_>,
+    v_metrics: VMetrics,
+) -> (f32, f32) {
+    let fallback = (v_metrics.ascent - v_metrics.descent, v_metrics.ascent);
+    let face = match face {
+        Some(face) => face,
+        None => return fallback,
+    };
+    let id = ttf_parser::GlyphId(glyph.id().0 as u16);
+    let advance = match face.glyph_ver_advance(id) {
+        Some(advance) => f32::from(advance),
+        None => return fallback,
+    };
+    // rusttype scales fonts so that the distance between their ascent and descent is the height
+    // of the scale, rather than by the units per em.
+    let unscaled = font.v_metrics_unscaled();
+    let units = glyph.scale().y / (unscaled.ascent - unscaled.descent);
+    let baseline = match (face.glyph_ver_side_bearing(id), glyph.exact_bounding_box()) {
+        (Some(bearing), Some(bounds)) => f32::from(bearing) * units - bounds.min.y,
+        _ => v_metrics.ascent,
+    };
+    (advance * units, baseline)
+}
+
+/// Whether a character is drawn upright in vertical text. This approximates the vertical
+/// orientation property of Unicode Standard Annex #50: ideographs, kana, Hangul and fullwidth
+/// forms are upright, except for brackets and the long vowel mark, which are rotated like other
+/// characters.
+fn is_upright(c: char) -> bool {
+    match c {
+        '\u{3008}'..='\u{3011}' | '\u{3014}'..='\u{301F}' | '\u{30FC}' => false,
+        '\u{FF08}' | '\u{FF09}' | '\u{FF3B}' | '\u{FF3D}' | '\u{FF5B}'..='\u{FF60}' => false,
+        '\u{1100}'..='\u{11FF}'
+        | '\u{2E80}'..='\u{A4CF}'
+        | '\u{A960}'..='\u{A97F}'
+        | '\u{AC00}'..='\u{D7FF}'
+        | '\u{F900}'..='\u{FAFF}'
+        | '\u{FE10}'..='\u{FE1F}'
+        | '\u{FE30}'..='\u{FE4F}'
+        | '\u{FF00}'..='\u{FFEF}'
+        | '\u{20000}'..='\u{3FFFD}' => true,
+        _ => false,
+    }
+}
+
+/// Get the visual position of each glyph in a line from its embedding level, following rule L2
+/// of the bidirectional algorithm.
+fn visual_order(levels: &[u8]) -> Vec<usize> {
+    let mut order: Vec<usize> = (0..levels.len()).collect();
+    let max = levels.iter().cloned().max().unwrap_or(0);
+    let min_odd = levels.iter().cloned().min().unwrap_or(0) | 1;
+
+    // From the highest level down to the lowest odd level, reverse every sequence of glyphs at
+    // that level or higher.
+    for level in (min_odd..=max).rev() {
+        let mut i = 0;
+        while i < order.len() {
+            if levels[order[i]] < level {
+                i += 1;
+                continue;
+            }
+            let start = i;
+            while i < order.len() && levels[order[i]] >= level {
+                i += 1;
+            }
+            order[start..i].reverse();
+        }
+    }
+
+    let mut positions = vec![0; levels.len()];
+    for (position, &i) in order.iter().enumerate() {
+        positions[i] = position;
+    }
+    positions
+}
+
+/// Break a paragraph starting at byte `offset` of the text into lines no wider than the maximum
+/// width of the layout. Whitespace at the end of a line may extend past the maximum width.
+/// `style_indices` holds the style of each byte of the paragraph, and if it is empty its line is
+/// spaced using `v_metrics`.
+fn wrap<'font>(
+    text: &str,
+    offset: usize,
+    styles: &[Style<'_, 'font>],
+    style_indices: &[usize],
+    v_metrics: VMetrics,
+    layout: &Layout,
+) -> Vec<Line<'font>> {
+    let bidi = BidiInfo::new(text, None);
+    let para_level = bidi
+        .paragraphs
+        .first()
+        .map_or(0, |para| para.level.number());
+    let levels: Vec<u8> = bidi.levels.iter().map(|level| level.number()).collect();
+
+    let max_width = layout.max_width.unwrap_or(f32::INFINITY);
+    let mut lines = Vec::new();
+    let mut line = Line::default();
+    let mut x = 0.0;
+    let mut prev = None;
+
+    for (start, word) in words(text) {
+        let range = start..start + word.len();
+        let glyphs = word_glyphs(
+            word,
+            offset + start,
+            &levels[range.clone()],
+            &style_indices[range],
+            styles,
+            layout,
+            &mut prev,
+        );
+        let width: f32 = glyphs
+            .iter()
+            .filter(|gly| !gly.whitespace)
+            .map(|gly| gly.kern + gly.advance)
+            .sum();
+
+        if x + width > max_width && !line.glyphs.is_empty() {
+            lines.push(mem::replace(&mut line, Line::default()));
+            x = 0.0;
+        }
+        line.words += 1;
+        for mut gly in glyphs {
+            if !gly.whitespace && x + gly.kern + gly.advance > max_width && !line.glyphs.is_empty()
+            {
+                lines.push(mem::replace(&mut line, Line::default()));
+                line.words = 1;
+                x = 0.0;
+            }
+            if line.glyphs.is_empty() {
+                gly.kern = 0.0;
+            }
+            x += gly.kern + gly.advance;
+            if !gly.whitespace {
+                line.width = x;
+            }
+            let metrics = styles[gly.style].v_metrics;
+            line.ascent = line.ascent.max(metrics.ascent);
+            line.descent = line.descent.min(metrics.descent);
+            line.line_gap = line.line_gap.max(metrics.line_gap);
+            gly.word = line.words - 1;
+            line.glyphs.push(gly);
+        }
+    }
+    if line.glyphs.is_empty() {
+        line.ascent = v_metrics.ascent;
+        line.descent = v_metrics.descent;
+        line.line_gap = v_metrics.line_gap;
+    }
+    line.last = true;
+    lines.push(line);
+    for line in &mut lines {
+        line.level = para_level;
+    }
+    lines
+}
+
+/// Get the glyphs for a word starting at byte `offset` of the text, which is split into runs of
+/// characters taken from the same font with the same style at the same embedding level. `levels`
+/// and `style_indices` hold the level and style of each byte of the word, and `prev` holds the
+/// last glyph of the previous word, for kerning.
+fn word_glyphs<'font>(
+    word: &str,
+    offset: usize,
+    levels: &[u8],
+    style_indices: &[usize],
+    styles: &[Style<'_, 'font>],
+    layout: &Layout,
+    prev: &mut Option<(FontId, Scale, GlyphId)>,
+) -> Vec<LineGlyph<'font>> {
+    let mut glyphs = Vec::new();
+    for (start, run, font) in font_runs(word, levels, style_indices, styles) {
+        let level = levels[start];
+        let style = style_indices[start];
+        let scale = styles[style].scale;
+
+        #[cfg(feature = "shaping")]
+        {
+            let shaped = shape_run(run, offset + start, level, style, font, scale, layout);
+            if let Some(shaped) = shaped {
+                glyphs.extend(shaped);
+                *prev = None;
+                continue;
+            }
+        }
+
+        for (i, c) in run.char_indices() {
+            // Characters such as brackets are mirrored in right-to-left runs, following rule L4 of
+            // the bidirectional algorithm. Shaping does this itself.
+            let mirrored = if level % 2 == 1 {
+                get_mirrored(c)
+            } else {
+                None
+            };
+            let glyph = font.font.glyph(mirrored.unwrap_or(c)).scaled(scale);
+            // Kerning is only defined between glyphs of the same font and scale, and pairs are
+            // only kerned in left-to-right runs where the logical and visual order agree.
+            let kern = match *prev {
+                Some((prev_font, prev_scale, prev_glyph))
+                    if layout.kerning
+                        && prev_font == font.id
+                        && prev_scale == scale
+                        && level % 2 == 0 =>
+                {
+                    font.font.pair_kerning(scale, prev_glyph, glyph.id())
+                }
+                _ => 0.0,
+            };
+            *prev = if level % 2 == 0 {
+                Some((font.id, scale, glyph.id()))
+            } else {
+                None
+            };
+            glyphs.push(LineGlyph {
+                advance: glyph.h_metrics().advance_width,
+                glyph,
+                font: font.id,
+                style,
+                kern,
+                offset: [0.0, 0.0],
+                source: offset + start + i..offset + start + i + c.len_utf8(),
+                level,
+                word: 0,
+                whitespace: c.is_whitespace(),
+            });
+        }
+    }
+    glyphs
+}
+
+/// Shape a run of text starting at byte `offset` of the text, if shaping is enabled and the raw
+/// data of the font is available.
+#[cfg(feature = "shaping")]
+fn shape_run<'font>(
+    run: &str,
+    offset: usize,
+    level: u8,
+    style: usize,
+    font: LayoutFont<'_, 'font>,
+    scale: Scale,
+    layout: &Layout,
+) -> Option<Vec<LineGlyph<'font>>> {
+    let shaped = shape(
+        font.data?,
+        font.font,
+        scale,
+        run,
+        level % 2 == 1,
+        layout.shaping.as_ref()?,
+    )?;
+    let mut clusters: Vec<usize> = shaped.iter().map(|gly| gly.cluster).collect();
+    clusters.sort();
+    clusters.dedup();
+    let glyphs = shaped
+        .into_iter()
+        .map(|gly| {
+            // A cluster runs up to the start of the next one in logical order.
+            let end = clusters
+                .iter()
+                .cloned()
+                .find(|&cluster| cluster > gly.cluster)
+                .unwrap_or(run.len());
+            LineGlyph {
+                glyph: font.font.glyph(gly.id).scaled(scale),
+                font: font.id,
+                style,
+                kern: 0.0,
+                advance: gly.advance,
+                offset: gly.offset,
+                source: offset + gly.cluster..offset + end,
+                level,
+                word: 0,
+                whitespace: run[gly.cluster..].starts_with(char::is_whitespace),
+            }
+        })
+        .collect();
+    Some(glyphs)
+}
+
+/// Split text into runs of characters which are taken from the same font with the same style at
+/// the same embedding level, along with the byte offset of each run.
+fn font_runs<'a, 'b, 'font>(
+    text: &'a str,
+    levels: &[u8],
+    style_indices: &[usize],
+    styles: &[Style<'b, 'font>],
+) -> Vec<(usize, &'a str, LayoutFont<'b, 'font>)> {
+    let mut runs = Vec::new();
+    let mut start = 0;
+    let mut current: Option<LayoutFont<'b, 'font>> = None;
+    for (i, c) in text.char_indices() {
+        let font = lookup(&styles[style_indices[i]].fonts, c);
+        match current {
+            Some(prev)
+                if prev.id == font.id
+                    && levels[start] == levels[i]
+                    && style_indices[start] == style_indices[i] => {}
+            Some(prev) => {
+                runs.push((start, &text[start..i], prev));
+                start = i;
+                current = Some(font);
+            }
+            None => current = Some(font),
+        }
+    }
+    if let Some(font) = current {
+        runs.push((start, &text[start..], font));
+    }
+    runs
+}
+
+/// Get the first font which contains a character. If none do, the first font is used so that its
+/// missing glyph is drawn.
+fn lookup<'a, 'font>(fonts: &[LayoutFont<'a, 'font>], c: char) -> LayoutFont<'a, 'font> {
+    fonts
+        .iter()
+        .cloned()
+        .find(|font| font.font.glyph(c).id() != GlyphId(0))
+        .unwrap_or(fonts[0])
+}
+
+/// Split text into words, each followed by any whitespace after it, along with the byte offset of
+/// each word.
+fn words(text: &str) -> Vec<(usize, &str)> {
+    let mut words = Vec::new();
+    let mut start = 0;
+    let mut after_whitespace = false;
+    for (i, c) in text.char_indices() {
+        if after_whitespace && !c.is_whitespace() {
+            words.push((start, &text[start..i]));
+            start = i;
+        }
+        after_whitespace = c.is_whitespace();
+    }
+    if start < text.len() {
+        words.push((start, &text[start..]));
+    }
+    words
+}
+
+/// Split text into paragraphs at line breaks, along with the byte offset of each paragraph.
+fn paragraphs(text: &str) -> Vec<(usize, &str)> {
+    text.lines()
+        .map(|line| (line.as_ptr() as usize - text.as_ptr() as usize, line))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    static MONO: &[u8] = include_bytes!("../tests/fonts/DejaVuSansMono.ttf");
+    static SERIF: &[u8] = include_bytes!("../tests/fonts/DejaVuSerif.ttf");
+
+    fn style<'a>(font: &'a Font<'static>, scale: Scale) -> Style<'a, 'static> {
+        let fonts = vec![LayoutFont {
+            id: FontId { brush: 0, index: 0 },
+            font,
+            data: None,
+        }];
+        Style::new(fonts, scale)
+    }
+
+    fn advance(font: &Font<'_>, c: char, scale: Scale) -> f32 {
+        font.glyph(c).scaled(scale).h_metrics().advance_width
+    }
+
+    /// Lay out text in a single style, returning the position of each glyph.
+    fn positions(font: &Font<'static>, text: &str, layout: &Layout, y_up: bool) -> Vec<[f32; 2]> {
+        let styles = [style(font, layout.scale)];
+        layout_glyphs(text, &styles, &vec![0; text.len()], layout, y_up)
+            .iter()
+            .map(|gly| [gly.glyph.position().x, gly.glyph.position().y])
+            .collect()
+    }
+
+    fn assert_close(left: &[f32], right: &[f32]) {
+        assert_eq!(left.len(), right.len(), "{:?} != {:?}", left, right);
+        for (l, r) in left.iter().zip(right) {
+            assert!((l - r).abs() < 1e-3, "{:?} != {:?}", left, right);
+        }
+    }
+
+    /// Wrap a paragraph in a single style, returning the number of glyphs and the width of each
+    /// line.
+    fn lines(font: &Font<'static>, text: &str, layout: &Layout) -> Vec<(usize, f32)> {
+        let styles = [style(font, layout.scale)];
+        let v_metrics = styles[0].v_metrics;
+        wrap(text, 0, &styles, &vec![0; text.len()], v_metrics, layout)
+            .iter()
+            .map(|line| (line.glyphs.len(), line.width))
+            .collect()
+    }
+
+    #[test]
+    fn lines_are_wrapped_between_words_at_the_max_width() {
+        let font = Font::from_bytes(MONO).unwrap();
+        let scale = Scale::uniform(20.0);
+        let w = advance(&font, 'a', scale);
+        let layout = Layout::new(scale, [0.0, 0.0]).with_max_width(w * 5.5);
+
+        assert_eq!(
+            lines(&font, "aaa bb cc", &layout),
+            [(4, w * 3.0), (5, w * 5.0)]
+        );
+        assert_eq!(
+            lines(&font, "aaa bb", &layout.clone().with_max_width(w * 6.0)),
+            [(6, w * 6.0)]
+        );
+    }
+
+    #[test]
+    fn long_words_are_broken_between_characters() {
+        let font = Font::from_bytes(MONO).unwrap();
+        let scale = Scale::uniform(20.0);
+        let w = advance(&font, 'a', scale);
+        let layout = Layout::new(scale, [0.0, 0.0]).with_max_width(w * 2.5);
+
+        assert_eq!(
+            lines(&font, "aaaaa", &layout),
+            [(2, w * 2.0), (2, w * 2.0), (1, w)]
+        );
+    }
+
+    #[test]
+    fn trailing_whitespace_takes_no_space() {
+        let font = Font::from_bytes(MONO).unwrap();
+        let scale = Scale::uniform(20.0);
+        let w = advance(&font, 'a', scale);
+        let layout = Layout::new(scale, [0.0, 0.0]).with_max_width(w * 2.5);
+
+        assert_eq!(
+            lines(&font, "aa    bb", &layout),
+            [(6, w * 2.0), (2, w * 2.0)]
+        );
+
+        let layout = Layout::new(scale, [100.0, 0.0]).with_h_align(HAlign::Right);
+        let xs: Vec<f32> = positions(&font, "ab  ", &layout, false)
+            .iter()
+            .map(|position| position[0])
+            .collect();
+        assert_eq!(xs, [100.0 - w * 2.0, 100.0 - w, 100.0, 100.0]);
+    }
+
+    #[test]
+    fn justified_lines_stretch_the_gaps_between_words() {
+        let font = Font::from_bytes(MONO).unwrap();
+        let scale = Scale::uniform(20.0);
+        let w = advance(&font, 'a', scale);
+        let layout = Layout::new(scale, [0.0, 0.0])
+            .with_max_width(w * 5.5)
+            .with_h_align(HAlign::Justify);
+
+        // The first line has two gaps sharing half a glyph of extra space, and the last line is
+        // left aligned.
+        let xs: Vec<f32> = positions(&font, "a b c d", &layout, false)
+            .iter()
+            .map(|position| position[0])
+            .collect();
+        let gap = w * 0.25;
+        assert_close(
+            &xs,
+            &[
+                0.0,
+                w,
+                w * 2.0 + gap,
+                w * 3.0 + gap,
+                w * 4.0 + gap * 2.0,
+                w * 5.0 + gap * 2.0,
+                0.0,
+            ],
+        );
+    }
+
+    #[test]
+    fn lines_are_stacked_towards_negative_y_when_y_points_up() {
+        let font = Font::from_bytes(MONO).unwrap();
+        let scale = Scale::uniform(20.0);
+        let v_metrics = font.v_metrics(scale);
+        let line_height = v_metrics.ascent - v_metrics.descent + v_metrics.line_gap;
+        let w = advance(&font, 'a', scale);
+        let layout = Layout::new(scale, [0.0, 100.0]).with_max_width(w * 2.5);
+
+        let baselines: Vec<f32> = positions(&font, "aa aa", &layout, true)
+            .iter()
+            .map(|position| position[1])
+            .collect();
+        let first = 100.0 - v_metrics.ascent;
+        let second = first - line_height;
+        assert_eq!(baselines, [first, first, first, second, second]);
+    }
+
+    #[test]
+    fn v_align_follows_the_direction_of_the_y_axis() {
+        let font = Font::from_bytes(MONO).unwrap();
+        let scale = Scale::uniform(20.0);
+        let v_metrics = font.v_metrics(scale);
+        let line_height = v_metrics.ascent - v_metrics.descent + v_metrics.line_gap;
+        let height = line_height + v_metrics.ascent - v_metrics.descent;
+
+        for &y_up in &[false, true] {
+            let down = if y_up { -1.0 } else { 1.0 };
+            for &(v_align, first) in &[
+                (VAlign::Top, v_metrics.ascent),
+                (VAlign::Middle, v_metrics.ascent - height / 2.0),
+                (VAlign::Baseline, 0.0),
+                (VAlign::Bottom, v_metrics.ascent - height),
+            ] {
+                let layout = Layout::new(scale, [0.0, 100.0]).with_v_align(v_align);
+                let baselines: Vec<f32> = positions(&font, "a\na", &layout, y_up)
+                    .iter()
+                    .map(|position| position[1])
+                    .collect();
+                let first = 100.0 + down * first;
+                let second = first + down * line_height;
+                assert_close(&baselines, &[first, second]);
+            }
+        }
+    }
+
+    #[test]
+    fn pairs_are_kerned_unless_disabled() {
+        let font = Font::from_bytes(SERIF).unwrap();
+        let scale = Scale::uniform(20.0);
+        let kern = font.pair_kerning(scale, font.glyph('A').id(), font.glyph('V').id());
+        assert!(kern < 0.0);
+
+        let layout = Layout::new(scale, [0.0, 0.0]);
+        let a = advance(&font, 'A', scale);
+        assert_eq!(positions(&font, "AV", &layout, false)[1][0], a + kern);
+        let layout = layout.with_kerning(false);
+        assert_eq!(positions(&font, "AV", &layout, false)[1][0], a);
+    }
+
+    #[test]
+    fn glyphs_record_their_source_in_visual_order() {
+        let font = Font::from_bytes(MONO).unwrap();
+        let layout = Layout::new(Scale::uniform(20.0), [0.0, 0.0]);
+        let styles = [style(&font, layout.scale)];
+        let text = "ab \u{5D0}\u{5D1}";
+        let sources: Vec<Range<usize>> =
+            layout_glyphs(text, &styles, &vec![0; text.len()], &layout, false)
+                .into_iter()
+                .map(|gly| gly.source)
+                .collect();
+        assert_eq!(sources, [0..1, 1..2, 2..3, 5..7, 3..5]);
+    }
+
+    #[test]
+    fn columns_are_broken_at_the_max_height() {
+        let font = Font::from_bytes(MONO).unwrap();
+        let scale = Scale::uniform(20.0);
+        let w = advance(&font, 'a', scale);
+        let layout = Layout::new(scale, [100.0, 0.0])
+            .with_writing_mode(WritingMode::VerticalRl)
+            .with_max_width(w)
+            .with_max_height(w * 2.5);
+
+        // Rotated glyphs advance down the column by their width, and the maximum width is
+        // ignored.
+        let ys: Vec<f32> = positions(&font, "aaa", &layout, false)
+            .iter()
+            .map(|position| position[1])
+            .collect();
+        assert_eq!(ys, [0.0, w, 0.0]);
+    }
+
+    /// Add `vhea` and `vmtx` tables to a font which has neither, giving every glyph the same
+    /// vertical advance and top side bearing in font units.
+    fn with_vertical_metrics(data: &[u8], advance: u16, bearing: i16) -> Vec<u8> {
+        let read_u16 = |offset: usize| u16::from_be_bytes([data[offset], data[offset + 1]]);
+        let read_u32 = |offset: usize| {
+            u32::from_be_bytes([
+                data[offset],
+                data[offset + 1],
+                data[offset + 2],
+                data[offset + 3],
+            ])
+        };
+        let num_tables = read_u16(4) as usize;
+        let records = 12..12 + num_tables * 16;
+        let num_glyphs = (0..num_tables)
+            .map(|i| records.start + i * 16)
+            .find(|&record| &data[record..record + 4] == b"maxp")
+            .map(|record| read_u16(read_u32(record + 8) as usize + 4))
+            .unwrap();
+
+        let mut vhea = vec![0; 36];
+        vhea[0..4].copy_from_slice(&0x0001_1000u32.to_be_bytes());
+        vhea[34..36].copy_from_slice(&1u16.to_be_bytes());
+        let mut vmtx = Vec::new();
+        vmtx.extend_from_slice(&advance.to_be_bytes());
+        for _ in 0..num_glyphs {
+            vmtx.extend_from_slice(&bearing.to_be_bytes());
+        }
+
+        // Move everything after the table directory along to make room for two more records,
+        // then append the tables at the end of the file.
+        let mut font = data[..12].to_vec();
+        font[4..6].copy_from_slice(&(num_tables as u16 + 2).to_be_bytes());
+        for record in records.clone().step_by(16) {
+            font.extend_from_slice(&data[record..record + 8]);
+            font.extend_from_slice(&(read_u32(record + 8) + 32).to_be_bytes());
+            font.extend_from_slice(&data[record + 12..record + 16]);
+        }
+        let mut body = data[records.end..].to_vec();
+        let start = font.len() + 32;
+        for (tag, table) in [(b"vhea", &vhea), (b"vmtx", &vmtx)].iter() {
+            body.resize(body.len() + (4 - body.len() % 4) % 4, 0);
+            font.extend_from_slice(&tag[..]);
+            font.extend_from_slice(&[0; 4]);
+            font.extend_from_slice(&((start + body.len()) as u32).to_be_bytes());
+            font.extend_from_slice(&(table.len() as u32).to_be_bytes());
+            body.extend_from_slice(table);
+        }
+        font.extend(body);
+        font
+    }
+
+    #[test]
+    fn upright_glyphs_advance_by_their_vertical_metrics() {
+        let data = with_vertical_metrics(MONO, 1500, 100);
+        let font = Font::from_bytes(data.clone()).unwrap();
+        let scale = Scale::uniform(20.0);
+        let layout = Layout::new(scale, [100.0, 0.0]).with_writing_mode(WritingMode::VerticalRl);
+        let unscaled = font.v_metrics_unscaled();
+        let units = scale.y / (unscaled.ascent - unscaled.descent);
+        let glyph = font.glyph('\u{6F22}').scaled(scale);
+        let bounds = glyph.exact_bounding_box().unwrap();
+
+        let mut styles = [style(&font, scale)];
+        styles[0].fonts[0].data = Some(&data);
+        let ys: Vec<f32> = layout_glyphs("\u{6F22}\u{6F22}", &styles, &[0; 6], &layout, false)
+            .iter()
+            .map(|gly| gly.glyph.position().y)
+            .collect();
+        let baseline = 100.0 * units - bounds.min.y;
+        assert_close(&ys, &[baseline, baseline + 1500.0 * units]);
+
+        // Without the font data, upright glyphs are as tall as the font.
+        let v_metrics = font.v_metrics(scale);
+        let ys: Vec<f32> = positions(&font, "\u{6F22}\u{6F22}", &layout, false)
+            .iter()
+            .map(|position| position[1])
+            .collect();
+        let height = v_metrics.ascent - v_metrics.descent;
+        assert_close(&ys, &[v_metrics.ascent, v_metrics.ascent + height]);
+    }
+
+    #[test]
+    fn brackets_are_mirrored_in_right_to_left_text() {
+        let font = Font::from_bytes(MONO).unwrap();
+        let layout = Layout::new(Scale::uniform(20.0), [0.0, 0.0]);
+        let styles = [style(&font, layout.scale)];
+        let text = "(\u{5D0})";
+        let ids: Vec<GlyphId> = layout_glyphs(text, &styles, &vec![0; text.len()], &layout, false)
+            .iter()
+            .map(|gly| gly.glyph.id())
+            .collect();
+        let id = |c| font.glyph(c).id();
+        assert_eq!(ids, [id('('), id('\u{5D0}'), id(')')]);
+    }
+
+    #[cfg(feature = "shaping")]
+    #[test]
+    fn shaped_glyphs_record_the_source_of_their_cluster() {
+        let font = Font::from_bytes(SERIF).unwrap();
+        let layout = Layout::new(Scale::uniform(20.0), [0.0, 0.0]).with_shaping(Shaping::new());
+        let mut styles = [style(&font, layout.scale)];
+        styles[0].fonts[0].data = Some(SERIF);
+
+        // The ligature covers both of its characters, and the right-to-left run is still drawn
+        // in visual order.
+        let text = "fix \u{5D0}\u{5D1}";
+        let glyphs = layout_glyphs(text, &styles, &vec![0; text.len()], &layout, false);
+        let sources: Vec<Range<usize>> = glyphs.iter().map(|gly| gly.source.clone()).collect();
+        assert_eq!(sources, [0..2, 2..3, 3..4, 6..8, 4..6]);
+        let xs: Vec<f32> = glyphs.iter().map(|gly| gly.glyph.position().x).collect();
+        assert!(xs.windows(2).all(|pair| pair[0] < pair[1]), "{:?}", xs);
+    }
+
+    #[test]
+    fn visual_order_reverses_right_to_left_runs() {
+        assert_eq!(visual_order(&[]), []);
+        assert_eq!(visual_order(&[0, 0, 0]), [0, 1, 2]);
+        assert_eq!(visual_order(&[1, 1, 1]), [2, 1, 0]);
+        assert_eq!(visual_order(&[0, 0, 1, 1, 1, 0]), [0, 1, 4, 3, 2, 5]);
+        // A left-to-right run embedded in a right-to-left one keeps its own order.
+        assert_eq!(visual_order(&[0, 1, 1, 2, 2, 1, 0]), [0, 5, 4, 2, 3, 1, 6]);
+    }
+
+    #[test]
+    fn paragraphs_are_split_at_line_breaks() {
+        assert_eq!(
+            paragraphs("ab\ncd\r\n\nef"),
+            [(0, "ab"), (3, "cd"), (7, ""), (8, "ef")]
+        );
+        assert_eq!(paragraphs(""), []);
+    }
+
+    #[test]
+    fn cjk_characters_are_upright_except_brackets() {
+        for &c in &['漢', 'か', 'カ', '한', 'Ａ', '、'] {
+            assert!(is_upright(c), "{:?}", c);
+        }
+        for &c in &['a', 'ا', '「', '（', 'ー'] {
+            assert!(!is_upright(c), "{:?}", c);
+        }
+    }
+}
@@ -1,44 +1,98 @@
 //! This crate provides integration of `vulkano` with the font caching feature of `rusttype`, and
 //! a basic pipeline for drawing text to the screen.
 
+mod buffer;
 mod cache;
+mod decoration;
 mod draw;
 mod error;
+mod layout;
+#[cfg(feature = "markup")]
+mod markup;
+mod offscreen;
+mod section;
+#[cfg(feature = "shaping")]
+mod shaping;
 
+pub use self::buffer::TextBuffer;
 pub use self::cache::GpuCache;
+pub use self::decoration::Decorations;
+pub use self::draw::{Coordinates, TextVertex};
 pub use self::error::{Error, ErrorKind, Result};
+pub use self::layout::{HAlign, Layout, Span, VAlign, WritingMode};
+#[cfg(feature = "markup")]
+pub use self::markup::{Markup, MarkupError};
+pub use self::section::{Gradient, GradientSpace, Section};
+#[cfg(feature = "shaping")]
+pub use self::shaping::Shaping;
+#[cfg(feature = "shaping")]
+pub use rustybuzz::{Language, Script};
 
-use std::ops::Range;
+use std::iter;
+use std::sync::atomic::{AtomicUsize, Ordering};
 use std::sync::Arc;
 
-use rusttype::PositionedGlyph;
+use rusttype::{point, Font, PositionedGlyph, Scale, SharedBytes};
+use vulkano::buffer::{BufferUsage, ImmutableBuffer};
 use vulkano::command_buffer::{
     AutoCommandBuffer, AutoCommandBufferBuilder, CommandBufferExecFuture, DynamicState,
 };
 use vulkano::device::Device;
 use vulkano::device::Queue;
+use vulkano::format::R8G8B8A8Unorm;
 use vulkano::framebuffer::{RenderPassAbstract, Subpass};
-use vulkano::sync::NowFuture;
+use vulkano::image::AttachmentImage;
+use vulkano::instance::QueueFamily;
+use vulkano::pipeline::shader::GraphicsEntryPointAbstract;
+use vulkano::sync::{GpuFuture, NowFuture};
 
-use crate::draw::Draw;
+use crate::decoration::LineMetrics;
+use crate::draw::{text_vertices, Draw};
+use crate::layout::{layout_glyphs, LayoutFont, Style};
+use crate::offscreen::Offscreen;
+use crate::section::QueuedGlyph;
 
-/// A unique identifier representing a font. Assigning each `Font` a `FontId`
-/// is left to the user.
-pub type FontId = usize;
+/// Identifies a font added to a `GlyphBrush` with `GlyphBrush::add_font`.
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash, Ord, PartialOrd)]
+pub struct FontId {
+    /// The id of the brush the font was added to.
+    pub(crate) brush: usize,
+    pub(crate) index: usize,
+}
+
+impl FontId {
+    /// The index of the font within its brush, under which its glyphs are stored in the
+    /// `GpuCache` returned by `GlyphBrush::cache`.
+    pub fn index(self) -> usize {
+        self.index
+    }
+}
+
+/// The id of the next `GlyphBrush` to be created, so that fonts added to one brush are not
+/// mistaken for those of another.
+static NEXT_BRUSH_ID: AtomicUsize = AtomicUsize::new(0);
+
+/// A font added to a `GlyphBrush`.
+pub(crate) struct RegisteredFont<'font> {
+    pub(crate) font: Font<'font>,
+    fallbacks: Vec<FontId>,
+    /// The underline and strikeout metrics from the font's tables, if they could be read.
+    pub(crate) line_metrics: LineMetrics,
+    /// The raw font data, if the font was added with `GlyphBrush::add_font_bytes`.
+    data: Option<SharedBytes<'font>>,
+}
 
 /// Object responsible for drawing text to the screen.
 pub struct GlyphBrush<'font> {
-    glyphs: Vec<PositionedGlyph<'font>>,
+    id: usize,
+    glyphs: Vec<QueuedGlyph<'font>>,
+    fonts: Vec<RegisteredFont<'font>>,
     cache: GpuCache<'font>,
+    cache_count: u64,
+    hidpi_factor: f32,
+    coordinates: Coordinates,
     draw: Draw,
-}
-
-/// An index for a range of glyphs with the same colour and font.
-#[derive(Clone, Debug)]
-pub struct Section {
-    font: FontId,
-    color: [f32; 4],
-    range: Range<usize>,
+    offscreen: Option<Offscreen>,
 }
 
 impl<'font> GlyphBrush<'font> {
@@ -48,29 +102,370 @@ impl<'font> GlyphBrush<'font> {
         subpass: Subpass<Arc<dyn RenderPassAbstract + Send + Sync>>,
     ) -> Result<Self> {
         let draw = Draw::new(device, subpass)?;
+        GlyphBrush::from_draw(device, draw)
+    }
+
+    /// Create a new `GlyphBrush` which uses a custom fragment shader. The built-in vertex shader
+    /// is used, so the shader must declare and read all twelve of its outputs, as described in
+    /// `GlyphBrush::with_shaders`. See the `custom_shader` example for a minimal shader.
+    pub fn with_fragment_shader<Fs>(
+        device: &Arc<Device>,
+        subpass: Subpass<Arc<dyn RenderPassAbstract + Send + Sync>>,
+        fs: Fs,
+    ) -> Result<Self>
+    where
+        Fs: GraphicsEntryPointAbstract<SpecializationConstants = ()>,
+        Fs::PipelineLayout: Clone + Send + Sync + 'static,
+    {
+        let draw = Draw::with_fragment_shader(device, subpass, fs)?;
+        GlyphBrush::from_draw(device, draw)
+    }
+
+    /// Create a new `GlyphBrush` which uses a custom vertex and fragment shader.
+    ///
+    /// Each glyph is drawn as one instance of a four vertex triangle strip. The brush pushes the
+    /// transform and binds the glyph cache for every draw, so the shaders must declare both:
+    ///
+    /// - The vertex shader must declare
+    ///   `layout(push_constant) uniform PushConstants { mat4 transform; } uniforms;`, which
+    ///   converts pixel coordinates to normalized device coordinates.
+    /// - At least one of the shaders must declare
+    ///   `layout(set = 0, binding = 0) uniform sampler2D font_tex;`, the glyph cache. Its red
+    ///   channel holds the coverage of each glyph. No other descriptors may be declared.
+    ///
+    /// The vertex shader receives the following per-instance inputs, which must have these names:
+    ///
+    /// | Location | Input                   | Description                                       |
+    /// |----------|-------------------------|---------------------------------------------------|
+    /// | 0        | `vec2 tl`               | Top left corner of the quad, in pixels.           |
+    /// | 1        | `vec2 br`               | Bottom right corner of the quad, in pixels.       |
+    /// | 2        | `vec2 tex_tl`           | Top left corner of the glyph in the cache.        |
+    /// | 3        | `vec2 tex_br`           | Bottom right corner of the glyph in the cache.    |
+    /// | 4        | `uint kind`             | 0 for a glyph, 1 for a solid rectangle.           |
+    /// | 5        | `vec4 color`            | The fill colour, or the first gradient colour.    |
+    /// | 6        | `vec4 outline_color`    | The outline colour.                               |
+    /// | 7        | `float outline_width`   | The outline width, in pixels.                     |
+    /// | 8        | `float blur`            | The blur radius of a shadow, in pixels.           |
+    /// | 9        | `uint gradient_kind`    | 0 for none, 1 for linear, 2 for radial.           |
+    /// | 10       | `vec4 gradient`         | The gradient endpoints, or centre and radius.     |
+    /// | 11       | `vec4 gradient_color`   | The second gradient colour.                       |
+    /// | 12       | `vec4 params`           | The parameters set by `Section::with_params`.     |
+    /// | 13       | `uint rotated`          | 1 if the glyph is turned clockwise in the quad.   |
+    ///
+    /// The quad is grown by the outline width and blur radius, rounded up, on each side. The
+    /// built-in vertex shader extends the texture coordinates to match, turns them a quarter turn
+    /// for rotated glyphs, and passes the following outputs to the fragment shader:
+    ///
+    /// | Location | Output                       | Description                                  |
+    /// |----------|------------------------------|----------------------------------------------|
+    /// | 0        | `vec2 f_tex_pos`             | The interpolated texture coordinate.         |
+    /// | 1        | `vec4 f_color`               | `color`                                      |
+    /// | 2        | `flat vec4 f_tex_rect`       | `tex_tl` and `tex_br`.                       |
+    /// | 3        | `flat vec4 f_outline_color`  | `outline_color`                              |
+    /// | 4        | `flat float f_outline_width` | `outline_width`                              |
+    /// | 5        | `flat float f_blur`          | `blur`                                       |
+    /// | 6        | `vec2 f_pos`                 | The interpolated position, in pixels.        |
+    /// | 7        | `flat uint f_gradient_kind`  | `gradient_kind`                              |
+    /// | 8        | `flat vec4 f_gradient`       | `gradient`                                   |
+    /// | 9        | `flat vec4 f_gradient_color` | `gradient_color`                             |
+    /// | 10       | `flat uint f_kind`           | `kind`                                       |
+    /// | 11       | `flat vec4 f_params`         | `params`                                     |
+    ///
+    /// vulkano requires the outputs of the vertex shader to match the inputs of the fragment
+    /// shader exactly, so a fragment shader used with the built-in vertex shader must declare
+    /// every one of these inputs at the same location and with the same type. It must also read
+    /// each of them, because inputs which are never read are left out of the compiled shader.
+    /// Otherwise, creating the brush fails with a `GraphicsPipelineCreation` error.
+    ///
+    /// Neither shader may use specialization constants.
+    pub fn with_shaders<Vs, Fs>(
+        device: &Arc<Device>,
+        subpass: Subpass<Arc<dyn RenderPassAbstract + Send + Sync>>,
+        vs: Vs,
+        fs: Fs,
+    ) -> Result<Self>
+    where
+        Vs: GraphicsEntryPointAbstract<SpecializationConstants = ()>,
+        Vs::PipelineLayout: Clone + Send + Sync + 'static,
+        Fs: GraphicsEntryPointAbstract<SpecializationConstants = ()>,
+        Fs::PipelineLayout: Clone + Send + Sync + 'static,
+    {
+        let draw = Draw::with_shaders(device, subpass, vs, fs)?;
+        GlyphBrush::from_draw(device, draw)
+    }
+
+    fn from_draw(device: &Arc<Device>, draw: Draw) -> Result<Self> {
         let cache = GpuCache::new(device)?;
         Ok(GlyphBrush {
+            id: NEXT_BRUSH_ID.fetch_add(1, Ordering::Relaxed),
             draw,
             cache,
+            cache_count: 0,
+            hidpi_factor: 1.0,
+            coordinates: Coordinates::default(),
             glyphs: Vec::new(),
+            fonts: Vec::new(),
+            offscreen: None,
         })
     }
 
+    /// Set the ratio of physical pixels to logical pixels, for example from
+    /// `winit::Window::get_hidpi_factor`. Glyphs queued afterwards are positioned and sized in
+    /// logical pixels, but rasterized at the physical resolution. The default is 1.
+    pub fn set_hidpi_factor(&mut self, hidpi_factor: f32) {
+        self.hidpi_factor = hidpi_factor;
+    }
+
+    /// The ratio of physical pixels to logical pixels used when queueing glyphs.
+    pub fn hidpi_factor(&self) -> f32 {
+        self.hidpi_factor
+    }
+
+    /// Set the coordinate system glyphs are positioned in. Whether the y axis points up is
+    /// recorded by sections queued afterwards, while the mapping to normalized device coordinates
+    /// applies to all later draws. The default is `Coordinates::TopLeft`.
+    pub fn set_coordinates(&mut self, coordinates: Coordinates) {
+        self.coordinates = coordinates;
+        self.draw.set_coordinates(coordinates);
+    }
+
+    /// The coordinate system glyphs are positioned in.
+    pub fn coordinates(&self) -> Coordinates {
+        self.coordinates
+    }
+
+    /// Add a font to this brush, returning the id used to queue text with it.
+    pub fn add_font(&mut self, font: Font<'font>) -> FontId {
+        self.fonts.push(RegisteredFont {
+            font,
+            fallbacks: Vec::new(),
+            line_metrics: LineMetrics::default(),
+            data: None,
+        });
+        FontId {
+            brush: self.id,
+            index: self.fonts.len() - 1,
+        }
+    }
+
+    /// Load a font from the contents of a font file and add it to this brush. Unlike
+    /// `GlyphBrush::add_font`, the data is kept so that text using the font can be shaped, and so
+    /// that decorations and vertical text can use metrics from tables `rusttype` does not read.
+    pub fn add_font_bytes<B>(&mut self, bytes: B) -> Result<FontId>
+    where
+        B: Into<SharedBytes<'font>>,
+    {
+        let bytes = bytes.into();
+        let line_metrics = LineMetrics::from_data(&bytes);
+        let data = bytes.clone();
+        let id = self.add_font(Font::from_bytes(bytes)?);
+        self.fonts[id.index].line_metrics = line_metrics;
+        self.fonts[id.index].data = Some(data);
+        Ok(id)
+    }
+
+    /// Set the fonts to try, in order, for characters missing from `font` when laying out text
+    /// with `GlyphBrush::queue_text`. This replaces any previous fallbacks of `font`, and the
+    /// fallbacks of the fallback fonts themselves are not used.
+    ///
+    /// # Panics
+    ///
+    /// Panics if any of the fonts were added to a different brush.
+    pub fn set_fallbacks(&mut self, font: FontId, fallbacks: &[FontId]) {
+        self.check_font(font);
+        for &fallback in fallbacks {
+            self.check_font(fallback);
+        }
+        self.fonts[font.index].fallbacks = fallbacks.to_vec();
+    }
+
+    /// Get a font previously added to this brush.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the font was added to a different brush.
+    pub fn font(&self, id: FontId) -> &Font<'font> {
+        self.check_font(id);
+        &self.fonts[id.index].font
+    }
+
+    /// The cache holding the glyphs queued by this brush.
+    pub fn cache(&self) -> &GpuCache<'font> {
+        &self.cache
+    }
+
     /// Queue some glyphs for later drawing. The `Section` returned is valid until a later call
     /// to `GlyphBrush::clear`.
-    pub fn queue_glyphs<I>(&mut self, glyphs: I, font: FontId, color: [f32; 4]) -> Section
+    ///
+    /// Each glyph is drawn with the font identified by `font`, using its glyph id, scale and
+    /// position, regardless of the font it was created from.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the font was added to a different brush.
+    pub fn queue_glyphs<'a, I>(&mut self, glyphs: I, font: FontId, color: [f32; 4]) -> Section
+    where
+        I: IntoIterator<Item = PositionedGlyph<'a>>,
+    {
+        self.check_font(font);
+        self.queue(
+            glyphs
+                .into_iter()
+                .enumerate()
+                .map(|(i, gly)| QueuedGlyph::new(gly, font, i..i + 1)),
+            color,
+        )
+    }
+
+    /// Queue some glyphs for later drawing, each with its own colour. This allows text such as
+    /// highlighted source code to be drawn as a single `Section`. The `Section` returned is valid
+    /// until a later call to `GlyphBrush::clear`. See `GlyphBrush::queue_glyphs` for details.
+    pub fn queue_colored_glyphs<'a, I>(&mut self, glyphs: I, font: FontId) -> Section
+    where
+        I: IntoIterator<Item = (PositionedGlyph<'a>, [f32; 4])>,
+    {
+        self.check_font(font);
+        self.queue(
+            glyphs
+                .into_iter()
+                .enumerate()
+                .map(|(i, (gly, color))| QueuedGlyph {
+                    color: Some(color),
+                    ..QueuedGlyph::new(gly, font, i..i + 1)
+                }),
+            [1.0; 4],
+        )
+    }
+
+    /// Lay out a string and queue its glyphs for later drawing. Lines are broken at newlines, and
+    /// wrapped between words if the layout has a maximum width. The `Section` returned is valid
+    /// until a later call to `GlyphBrush::clear`. Unless the text is shaped, the section has one
+    /// glyph for each character of the string other than line breaks.
+    ///
+    /// Characters missing from `font` are taken from the first of its fallbacks, set with
+    /// `GlyphBrush::set_fallbacks`, which contains them. Lines are spaced using the metrics of
+    /// `font`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the font was added to a different brush.
+    pub fn queue_text(
+        &mut self,
+        text: &str,
+        font: FontId,
+        layout: &Layout,
+        color: [f32; 4],
+    ) -> Section {
+        let styles = [Style::new(self.layout_fonts(font), layout.scale)];
+        let glyphs = layout_glyphs(
+            text,
+            &styles,
+            &vec![0; text.len()],
+            layout,
+            self.coordinates.y_up(),
+        );
+        self.queue(glyphs, color)
+    }
+
+    /// Lay out some spans of text together and queue their glyphs for later drawing. Each span
+    /// has its own font, scale, colour and decorations, but the spans are wrapped and aligned as
+    /// one string, with the glyphs on each line sharing a baseline. The `Section` returned is
+    /// valid until a later call to `GlyphBrush::clear`, and its highlight ranges are in bytes of
+    /// the text of all the spans joined together. See `GlyphBrush::queue_text` for details.
+    ///
+    /// # Panics
+    ///
+    /// Panics if any of the fonts was added to a different brush.
+    pub fn queue_spans(&mut self, spans: &[Span<'_>], layout: &Layout, color: [f32; 4]) -> Section {
+        let mut text = String::new();
+        let mut styles = Vec::with_capacity(spans.len());
+        let mut style_indices = Vec::new();
+        for span in spans {
+            text.push_str(&span.text);
+            style_indices.extend(iter::repeat(styles.len()).take(span.text.len()));
+            let scale = span.scale.unwrap_or(layout.scale);
+            let mut style = Style::new(self.layout_fonts(span.font), scale);
+            style.color = span.color;
+            style.decorations = span.decorations;
+            styles.push(style);
+        }
+        let glyphs = layout_glyphs(
+            &text,
+            &styles,
+            &style_indices,
+            layout,
+            self.coordinates.y_up(),
+        );
+        self.queue(glyphs, color)
+    }
+
+    /// Parse a string of markup and queue the resulting spans for later drawing. Highlight ranges
+    /// of the `Section` returned are in bytes of the text with its tags removed, and each icon
+    /// and escaped `<` counts as the bytes of its character. See `Markup` for the supported tags
+    /// and `GlyphBrush::queue_spans` for details.
+    ///
+    /// # Panics
+    ///
+    /// Panics if any of the fonts used by `markup` was added to a different brush.
+    #[cfg(feature = "markup")]
+    pub fn queue_markup(
+        &mut self,
+        text: &str,
+        markup: &Markup,
+        layout: &Layout,
+        color: [f32; 4],
+    ) -> std::result::Result<Section, MarkupError> {
+        let spans = markup.parse(text)?;
+        Ok(self.queue_spans(&spans, layout, color))
+    }
+
+    /// Panic if a font was added to a different brush.
+    fn check_font(&self, font: FontId) {
+        assert!(font.brush == self.id, "font was added to a different brush");
+    }
+
+    /// Get a font followed by its fallbacks, for layout.
+    fn layout_fonts(&self, font: FontId) -> Vec<LayoutFont<'_, 'font>> {
+        self.check_font(font);
+        iter::once(font)
+            .chain(self.fonts[font.index].fallbacks.iter().cloned())
+            .map(|id| LayoutFont {
+                id,
+                font: &self.fonts[id.index].font,
+                data: self.fonts[id.index].data.as_ref().map(|data| &**data),
+            })
+            .collect()
+    }
+
+    /// Queue glyphs positioned in logical pixels, converting them to physical pixels.
+    fn queue<'a, I>(&mut self, glyphs: I, color: [f32; 4]) -> Section
     where
-        I: IntoIterator<Item = PositionedGlyph<'font>>,
+        I: IntoIterator<Item = QueuedGlyph<'a>>,
     {
         let old_len = self.glyphs.len();
-        self.glyphs.extend(glyphs);
+        let factor = self.hidpi_factor;
+        let fonts = &self.fonts;
+        self.glyphs
+            .extend(glyphs.into_iter().map(|gly| QueuedGlyph {
+                glyph: to_physical(&fonts[gly.font.index].font, &gly.glyph, factor),
+                font: gly.font,
+                color: gly.color,
+                decorations: gly.decorations,
+                source: gly.source,
+                rotated: gly.rotated,
+                vertical: gly.vertical,
+            }));
         let range = old_len..self.glyphs.len();
-        Section { range, font, color }
+        Section::new(range, color, factor, self.coordinates.y_up())
     }
 
     /// Cache some sections of text. If a future is returned, it should be executed before
-    /// drawing those sections. This may overwrite cached sections from previous calls to this
-    /// function.
+    /// drawing those sections. This may overwrite glyphs cached by previous calls to this function
+    /// or to `GlyphBrush::cache_text_buffers`.
+    ///
+    /// Because of this, every text buffer becomes outdated and must be cached again with
+    /// `GlyphBrush::cache_text_buffers` before it is drawn. To draw sections and text buffers in
+    /// the same frame, pass both to a single call to `GlyphBrush::cache_text_buffers` instead.
     pub fn cache_sections<'a, I>(
         &mut self,
         queue: &Arc<Queue>,
@@ -80,25 +475,145 @@ impl<'font> GlyphBrush<'font> {
         I: IntoIterator<Item = &'a Section>,
     {
         let glyphs = &self.glyphs;
+        self.cache_count += 1;
         self.cache.cache(
             queue,
             sections.into_iter().flat_map(|section| {
                 glyphs[section.range.clone()]
                     .iter()
-                    .map(move |gly| (section.font, gly.clone()))
+                    .map(|gly| (gly.font.index, gly.glyph.clone()))
             }),
         )
     }
 
+    /// Cache some sections of text along with the glyphs in some text buffers, rebuilding the
+    /// vertices of any buffers which are out of date. If a future is returned, it should be
+    /// executed before drawing those sections or buffers.
+    ///
+    /// Text buffers not included in this call must be cached again before they are drawn.
+    pub fn cache_text_buffers<'a, 'b, I, B>(
+        &mut self,
+        queue: &Arc<Queue>,
+        sections: I,
+        buffers: B,
+    ) -> Result<Option<Box<dyn GpuFuture + Send + Sync>>>
+    where
+        I: IntoIterator<Item = &'a Section>,
+        B: IntoIterator<Item = &'b mut TextBuffer<'font>>,
+    {
+        let buffers: Vec<&mut TextBuffer<'font>> = buffers.into_iter().collect();
+        let glyphs = &self.glyphs;
+        let section_glyphs = sections.into_iter().flat_map(|section| {
+            glyphs[section.range.clone()]
+                .iter()
+                .map(|gly| (gly.font.index, gly.glyph.clone()))
+        });
+        let buffer_glyphs = buffers.iter().flat_map(|buffer| {
+            buffer.sections.iter().flat_map(move |section| {
+                buffer.glyphs[section.range.clone()]
+                    .iter()
+                    .map(|gly| (gly.font.index, gly.glyph.clone()))
+            })
+        });
+
+        self.cache_count += 1;
+        let mut future = self
+            .cache
+            .cache(queue, section_glyphs.chain(buffer_glyphs))?
+            .map(|future| Box::new(future) as Box<dyn GpuFuture + Send + Sync>);
+
+        for buffer in buffers {
+            let outdated = buffer.cached.is_none() || buffer.generation != self.cache.generation();
+            buffer.cached = Some(self.cache_count);
+            if !outdated {
+                continue;
+            }
+
+            let vertices =
+                text_vertices(&buffer.glyphs, &buffer.sections, &self.fonts, &self.cache)?;
+            buffer.generation = self.cache.generation();
+            buffer.vbuf = if vertices.is_empty() {
+                None
+            } else {
+                let (vbuf, upload) = ImmutableBuffer::from_iter(
+                    vertices.into_iter(),
+                    BufferUsage::vertex_buffer(),
+                    Arc::clone(queue),
+                )?;
+                future = Some(match future {
+                    Some(future) => Box::new(future.join(upload)),
+                    None => Box::new(upload),
+                });
+                Some(vbuf)
+            };
+        }
+
+        Ok(future)
+    }
+
+    /// Create a `TextBuffer` holding a copy of some sections of text. The buffer remains valid
+    /// after a call to `GlyphBrush::clear`.
+    pub fn text_buffer<'a, I>(&self, sections: I) -> TextBuffer<'font>
+    where
+        I: IntoIterator<Item = &'a Section>,
+    {
+        TextBuffer::new(&self.glyphs, sections)
+    }
+
+    /// Draw a text buffer to the screen. The buffer must have been included in the latest call to
+    /// `GlyphBrush::cache_text_buffers`, with no call to `GlyphBrush::cache_sections` since,
+    /// as either may evict its glyphs. Otherwise this fails with `ErrorKind::OutdatedTextBuffer`.
+    pub fn draw_text_buffer(
+        &mut self,
+        cmd: AutoCommandBufferBuilder,
+        buffer: &TextBuffer<'font>,
+        state: &DynamicState,
+        transform: [[f32; 4]; 4],
+    ) -> Result<AutoCommandBufferBuilder> {
+        if buffer.cached != Some(self.cache_count) {
+            return Err(ErrorKind::OutdatedTextBuffer.into());
+        }
+
+        match &buffer.vbuf {
+            Some(vbuf) => self.draw.draw_instances(
+                cmd,
+                Arc::clone(vbuf),
+                vbuf.len() as u32,
+                &self.cache,
+                state,
+                transform,
+            ),
+            None => Ok(cmd),
+        }
+    }
+
+    /// Get the vertices used to draw some sections of text, for drawing them with a custom
+    /// pipeline. The sections should have been previously cached using
+    /// `GlyphBrush::cache_sections`, and the vertices are only valid until the cache is next
+    /// modified.
+    ///
+    /// Vertices are ordered as they would be drawn, and are positioned in physical pixels in the
+    /// coordinate system set by `GlyphBrush::set_coordinates`. Their texture coordinates refer to
+    /// `GpuCache::image`, which is available from `GlyphBrush::cache`.
+    pub fn vertices<'a, I>(&self, sections: I) -> Result<Vec<TextVertex>>
+    where
+        I: IntoIterator<Item = &'a Section>,
+    {
+        text_vertices(&self.glyphs, sections, &self.fonts, &self.cache)
+    }
+
     /// Draw a section of text to the screen. The section should have been previously cached
     /// using `GlyphBrush::cache_sections`.
+    ///
+    /// Glyph positions are converted to physical pixels using the hidpi factor. Unless the
+    /// coordinate system is `Coordinates::Raw`, they are then mapped to normalized device
+    /// coordinates using the first viewport of `state`, before `transform` is applied.
     pub fn draw<'a, I>(
         &mut self,
         cmd: AutoCommandBufferBuilder,
         sections: I,
         state: &DynamicState,
         transform: [[f32; 4]; 4],
-        dims: [f32; 2],
     ) -> Result<AutoCommandBufferBuilder>
     where
         I: IntoIterator<Item = &'a Section>,
@@ -107,16 +622,94 @@ impl<'font> GlyphBrush<'font> {
             cmd,
             &self.glyphs,
             sections,
+            &self.fonts,
             &self.cache,
             state,
             transform,
-            dims,
         )
     }
 
+    /// Draw some sections of text into a new image, cleared to transparent black. The sections
+    /// should have been previously cached using `GlyphBrush::cache_sections`, and glyph positions
+    /// are relative to the top left of the image, or the bottom left if y points up. The returned
+    /// future completes when drawing has finished.
+    ///
+    /// The image is drawn using the built-in shaders, even if this brush uses custom shaders.
+    pub fn draw_to_image<'a, I>(
+        &mut self,
+        queue: &Arc<Queue>,
+        sections: I,
+        dimensions: [u32; 2],
+    ) -> Result<(
+        Arc<AttachmentImage<R8G8B8A8Unorm>>,
+        CommandBufferExecFuture<NowFuture, AutoCommandBuffer>,
+    )>
+    where
+        I: IntoIterator<Item = &'a Section>,
+    {
+        let image = Offscreen::create_image(queue.device(), dimensions)?;
+        let future = self.draw_to_existing_image(queue, sections, Arc::clone(&image))?;
+        Ok((image, future))
+    }
+
+    /// Draw some sections of text into an existing image, which is cleared to transparent black
+    /// first. The image must have been created with the `color_attachment` usage. See
+    /// `GlyphBrush::draw_to_image` for details.
+    pub fn draw_to_existing_image<'a, I>(
+        &mut self,
+        queue: &Arc<Queue>,
+        sections: I,
+        image: Arc<AttachmentImage<R8G8B8A8Unorm>>,
+    ) -> Result<CommandBufferExecFuture<NowFuture, AutoCommandBuffer>>
+    where
+        I: IntoIterator<Item = &'a Section>,
+    {
+        let offscreen = match &mut self.offscreen {
+            Some(offscreen) => offscreen,
+            offscreen => offscreen.get_or_insert(Offscreen::new(self.draw.device())?),
+        };
+        offscreen.draw(
+            queue,
+            &self.glyphs,
+            sections,
+            &self.fonts,
+            &self.cache,
+            image,
+            self.coordinates,
+        )
+    }
+
+    /// Create a secondary command buffer builder which inherits the subpass this brush was created
+    /// for. Text can be drawn into it using `GlyphBrush::draw`, allowing it to be recorded in
+    /// parallel with the rest of a scene. The built command buffer should be executed within a
+    /// render pass begun with `secondary` set to `true`.
+    pub fn secondary_command_buffer(
+        &self,
+        queue_family: QueueFamily<'_>,
+    ) -> Result<AutoCommandBufferBuilder> {
+        self.draw.secondary(queue_family)
+    }
+
     /// Clear the internal glyph buffer. This invalidates all `Section` objects created by this
     /// `GlyphBrush`.
     pub fn clear(&mut self) {
         self.glyphs.clear();
     }
 }
+
+/// Convert a glyph positioned and scaled in logical pixels to physical pixels, looking it up in a
+/// registered font.
+fn to_physical<'font>(
+    font: &Font<'font>,
+    glyph: &PositionedGlyph<'_>,
+    factor: f32,
+) -> PositionedGlyph<'font> {
+    let position = glyph.position();
+    let scale = glyph.scale();
+    font.glyph(glyph.id())
+        .scaled(Scale {
+            x: scale.x * factor,
+            y: scale.y * factor,
+        })
+        .positioned(point(position.x * factor, position.y * factor))
+}
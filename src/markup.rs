@@ -0,0 +1,420 @@
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::{error, fmt};
+
+use rusttype::Scale;
+
+use crate::{FontId, Span};
+
+/// The fonts and icons used to turn a string of markup into `Span`s.
+///
+/// Markup is plain text containing the following tags, which may be nested:
+///
+/// | Tag                          | Effect                                                      |
+/// |------------------------------|-------------------------------------------------------------|
+/// | `<b>...</b>`                 | Use the bold font.                                          |
+/// | `<i>...</i>`                 | Use the italic font.                                        |
+/// | `<color=#rrggbb>...</color>` | Use a colour, written as 3, 4, 6 or 8 hexadecimal digits.   |
+/// | `<size=24>...</size>`        | Use a scale, in logical pixels.                             |
+/// | `<icon=name>`                | Insert an icon added with `Markup::with_icon`.              |
+///
+/// A literal `<` is written as `<<`.
+#[derive(Clone, Debug)]
+pub struct Markup {
+    pub(crate) regular: FontId,
+    pub(crate) bold: Option<FontId>,
+    pub(crate) italic: Option<FontId>,
+    pub(crate) bold_italic: Option<FontId>,
+    pub(crate) icons: HashMap<String, (FontId, char)>,
+}
+
+/// An error in a string of markup. Each variant holds the byte offset of the tag at fault.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum MarkupError {
+    /// A `<` was not followed by a matching `>`.
+    UnterminatedTag(usize),
+    /// A tag was not one of those supported by `Markup`.
+    UnknownTag(usize),
+    /// A tag was missing its value, had a value it does not take, or had a value which could not
+    /// be parsed.
+    InvalidValue(usize),
+    /// An icon tag named an icon which was not added with `Markup::with_icon`.
+    UnknownIcon(usize),
+    /// A closing tag did not match the most recently opened tag.
+    MismatchedTag(usize),
+    /// A tag was opened but never closed.
+    UnclosedTag(usize),
+    #[doc(hidden)]
+    __NonExhaustive,
+}
+
+/// A tag which is open at some point in the markup.
+#[derive(Copy, Clone, Debug, PartialEq)]
+enum Tag {
+    Bold,
+    Italic,
+    Color([f32; 4]),
+    Size(Scale),
+}
+
+impl Tag {
+    fn name(&self) -> &'static str {
+        match self {
+            Tag::Bold => "b",
+            Tag::Italic => "i",
+            Tag::Color(_) => "color",
+            Tag::Size(_) => "size",
+        }
+    }
+}
+
+impl Markup {
+    /// Parse markup using the given font for text outside of bold and italic tags.
+    pub fn new(regular: FontId) -> Self {
+        Markup {
+            regular,
+            bold: None,
+            italic: None,
+            bold_italic: None,
+            icons: HashMap::new(),
+        }
+    }
+
+    /// Use a font for bold text. If this is not set, bold text uses the regular font.
+    pub fn with_bold(mut self, font: FontId) -> Self {
+        self.bold = Some(font);
+        self
+    }
+
+    /// Use a font for italic text. If this is not set, italic text uses the regular font.
+    pub fn with_italic(mut self, font: FontId) -> Self {
+        self.italic = Some(font);
+        self
+    }
+
+    /// Use a font for text which is both bold and italic. If this is not set, the bold font is
+    /// used, or failing that the italic font.
+    pub fn with_bold_italic(mut self, font: FontId) -> Self {
+        self.bold_italic = Some(font);
+        self
+    }
+
+    /// Add an icon which can be inserted with `<icon=name>`. The icon is drawn as the glyph for
+    /// `c` in `font`, at the current scale and colour.
+    pub fn with_icon<S>(mut self, name: S, font: FontId, c: char) -> Self
+    where
+        S: Into<String>,
+    {
+        self.icons.insert(name.into(), (font, c));
+        self
+    }
+
+    /// Parse a string of markup into spans, which can be queued with `GlyphBrush::queue_spans`.
+    pub fn parse<'a>(&self, text: &'a str) -> Result<Vec<Span<'a>>, MarkupError> {
+        let mut spans = Vec::new();
+        let mut open: Vec<(usize, Tag)> = Vec::new();
+        let mut i = 0;
+
+        while let Some(offset) = text[i..].find('<') {
+            let start = i + offset;
+            if start > i {
+                spans.push(self.span(&text[i..start], None, &open));
+            }
+            if text[start + 1..].starts_with('<') {
+                spans.push(self.span(&text[start..=start], None, &open));
+                i = start + 2;
+                continue;
+            }
+
+            let end = match text[start..].find('>') {
+                Some(end) => start + end,
+                None => return Err(MarkupError::UnterminatedTag(start)),
+            };
+            let tag = &text[start + 1..end];
+            i = end + 1;
+
+            if tag.starts_with('/') {
+                match open.pop() {
+                    Some((_, open_tag)) if open_tag.name() == &tag[1..] => (),
+                    _ => return Err(MarkupError::MismatchedTag(start)),
+                }
+                continue;
+            }
+
+            let (name, value) = match tag.find('=') {
+                Some(eq) => (&tag[..eq], Some(&tag[eq + 1..])),
+                None => (tag, None),
+            };
+            let invalid = MarkupError::InvalidValue(start);
+            match (name, value) {
+                ("b", None) => open.push((start, Tag::Bold)),
+                ("i", None) => open.push((start, Tag::Italic)),
+                ("color", Some(value)) => {
+                    let color = parse_color(value).ok_or(invalid)?;
+                    open.push((start, Tag::Color(color)));
+                }
+                ("size", Some(value)) => {
+                    let size = value
+                        .parse::<f32>()
+                        .ok()
+                        .filter(|size| size.is_finite() && *size > 0.0)
+                        .ok_or(invalid)?;
+                    open.push((start, Tag::Size(Scale::uniform(size))));
+                }
+                ("icon", Some(value)) => {
+                    let &(font, c) = self
+                        .icons
+                        .get(value)
+                        .ok_or(MarkupError::UnknownIcon(start))?;
+                    spans.push(self.span(c.to_string(), Some(font), &open));
+                }
+                ("b", _) | ("i", _) | ("color", _) | ("size", _) | ("icon", _) => {
+                    return Err(invalid)
+                }
+                _ => return Err(MarkupError::UnknownTag(start)),
+            }
+        }
+        if i < text.len() {
+            spans.push(self.span(&text[i..], None, &open));
+        }
+
+        match open.last() {
+            Some(&(start, _)) => Err(MarkupError::UnclosedTag(start)),
+            None => Ok(spans),
+        }
+    }
+
+    /// Create a span styled by the open tags, using `font` if given or otherwise the font for
+    /// the current weight and style. Inner tags override outer ones.
+    fn span<'a, T>(&self, text: T, font: Option<FontId>, open: &[(usize, Tag)]) -> Span<'a>
+    where
+        T: Into<Cow<'a, str>>,
+    {
+        let bold = open.iter().any(|&(_, tag)| tag == Tag::Bold);
+        let italic = open.iter().any(|&(_, tag)| tag == Tag::Italic);
+        let font = font.unwrap_or_else(|| match (bold, italic) {
+            (true, true) => self
+                .bold_italic
+                .or(self.bold)
+                .or(self.italic)
+                .unwrap_or(self.regular),
+            (true, false) => self.bold.unwrap_or(self.regular),
+            (false, true) => self.italic.unwrap_or(self.regular),
+            (false, false) => self.regular,
+        });
+
+        let mut span = Span::new(text, font);
+        for &(_, tag) in open {
+            match tag {
+                Tag::Color(color) => span = span.with_color(color),
+                Tag::Size(scale) => span = span.with_scale(scale),
+                Tag::Bold | Tag::Italic => (),
+            }
+        }
+        span
+    }
+}
+
+/// Parse a colour written as `#rgb`, `#rgba`, `#rrggbb` or `#rrggbbaa`.
+fn parse_color(value: &str) -> Option<[f32; 4]> {
+    if !value.starts_with('#') || !value[1..].chars().all(|c| c.is_ascii_hexdigit()) {
+        return None;
+    }
+    let digits = &value[1..];
+    let (width, max) = match digits.len() {
+        3 | 4 => (1, 15.0),
+        6 | 8 => (2, 255.0),
+        _ => return None,
+    };
+
+    let mut color = [1.0; 4];
+    for (i, channel) in color.iter_mut().enumerate().take(digits.len() / width) {
+        let component = u8::from_str_radix(&digits[i * width..(i + 1) * width], 16).ok()?;
+        *channel = f32::from(component) / max;
+    }
+    Some(color)
+}
+
+impl fmt::Display for MarkupError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MarkupError::UnterminatedTag(pos) => write!(f, "unterminated tag at byte {}", pos),
+            MarkupError::UnknownTag(pos) => write!(f, "unknown tag at byte {}", pos),
+            MarkupError::InvalidValue(pos) => write!(f, "invalid tag value at byte {}", pos),
+            MarkupError::UnknownIcon(pos) => write!(f, "unknown icon at byte {}", pos),
+            MarkupError::MismatchedTag(pos) => write!(f, "mismatched closing tag at byte {}", pos),
+            MarkupError::UnclosedTag(pos) => write!(f, "unclosed tag at byte {}", pos),
+            MarkupError::__NonExhaustive => unreachable!(),
+        }
+    }
+}
+
+impl error::Error for MarkupError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const REGULAR: FontId = FontId { brush: 0, index: 0 };
+    const BOLD: FontId = FontId { brush: 0, index: 1 };
+    const ITALIC: FontId = FontId { brush: 0, index: 2 };
+    const BOLD_ITALIC: FontId = FontId { brush: 0, index: 3 };
+    const ICONS: FontId = FontId { brush: 0, index: 4 };
+
+    fn fonts(markup: &Markup, text: &str) -> Vec<(String, FontId)> {
+        markup
+            .parse(text)
+            .unwrap()
+            .into_iter()
+            .map(|span| (span.text.into_owned(), span.font))
+            .collect()
+    }
+
+    fn span(text: &str, font: FontId) -> (String, FontId) {
+        (text.to_owned(), font)
+    }
+
+    #[test]
+    fn nested_tags_select_fonts() {
+        let text = "a<b>b<i>c</i></b><i>d</i>";
+        let markup = Markup::new(REGULAR);
+        assert_eq!(
+            fonts(&markup, text),
+            [
+                span("a", REGULAR),
+                span("b", REGULAR),
+                span("c", REGULAR),
+                span("d", REGULAR)
+            ]
+        );
+
+        let markup = markup.with_italic(ITALIC);
+        assert_eq!(
+            fonts(&markup, text),
+            [
+                span("a", REGULAR),
+                span("b", REGULAR),
+                span("c", ITALIC),
+                span("d", ITALIC)
+            ]
+        );
+
+        let markup = markup.with_bold(BOLD);
+        assert_eq!(
+            fonts(&markup, text),
+            [
+                span("a", REGULAR),
+                span("b", BOLD),
+                span("c", BOLD),
+                span("d", ITALIC)
+            ]
+        );
+
+        let markup = markup.with_bold_italic(BOLD_ITALIC);
+        assert_eq!(
+            fonts(&markup, text),
+            [
+                span("a", REGULAR),
+                span("b", BOLD),
+                span("c", BOLD_ITALIC),
+                span("d", ITALIC)
+            ]
+        );
+    }
+
+    #[test]
+    fn inner_tags_override_outer_ones() {
+        let markup = Markup::new(REGULAR);
+        let spans = markup
+            .parse("<size=10><color=#f00>a<color=#00f><size=20>b</size></color>c</color></size>")
+            .unwrap();
+        let red = [1.0, 0.0, 0.0, 1.0];
+        let blue = [0.0, 0.0, 1.0, 1.0];
+        assert_eq!(
+            spans,
+            [
+                Span::new("a", REGULAR)
+                    .with_scale(Scale::uniform(10.0))
+                    .with_color(red),
+                Span::new("b", REGULAR)
+                    .with_scale(Scale::uniform(20.0))
+                    .with_color(blue),
+                Span::new("c", REGULAR)
+                    .with_scale(Scale::uniform(10.0))
+                    .with_color(red),
+            ]
+        );
+    }
+
+    #[test]
+    fn icons_are_inserted_with_their_font() {
+        let markup = Markup::new(REGULAR).with_icon("star", ICONS, '★');
+        assert_eq!(
+            fonts(&markup, "<b>a<icon=star></b>"),
+            [span("a", REGULAR), span("★", ICONS)]
+        );
+    }
+
+    #[test]
+    fn double_angle_brackets_are_escaped() {
+        let markup = Markup::new(REGULAR);
+        assert_eq!(
+            fonts(&markup, "a<<b><<<b>c</b>"),
+            [
+                span("a", REGULAR),
+                span("<", REGULAR),
+                span("b>", REGULAR),
+                span("<", REGULAR),
+                span("c", REGULAR)
+            ]
+        );
+    }
+
+    #[test]
+    fn colors_have_three_four_six_or_eight_digits() {
+        assert_eq!(parse_color("#f00"), Some([1.0, 0.0, 0.0, 1.0]));
+        assert_eq!(parse_color("#0f05"), Some([0.0, 1.0, 0.0, 1.0 / 3.0]));
+        assert_eq!(parse_color("#0000ff"), Some([0.0, 0.0, 1.0, 1.0]));
+        assert_eq!(parse_color("#FFFFFF33"), Some([1.0, 1.0, 1.0, 0.2]));
+
+        for &invalid in &[
+            "",
+            "#",
+            "f00",
+            "#ff",
+            "#ff000",
+            "#ff00000",
+            "#ff0000000",
+            "#ggg",
+        ] {
+            assert_eq!(parse_color(invalid), None, "{:?}", invalid);
+        }
+    }
+
+    #[test]
+    fn errors_hold_the_offset_of_the_tag() {
+        let markup = Markup::new(REGULAR).with_icon("star", ICONS, '★');
+        let cases = [
+            ("ab<b", MarkupError::UnterminatedTag(2)),
+            ("a<", MarkupError::UnterminatedTag(1)),
+            ("a<u>b</u>", MarkupError::UnknownTag(1)),
+            ("a<>", MarkupError::UnknownTag(1)),
+            ("ab<b=1>c</b>", MarkupError::InvalidValue(2)),
+            ("<size>a</size>", MarkupError::InvalidValue(0)),
+            ("<size=0>a</size>", MarkupError::InvalidValue(0)),
+            ("a<color=red>b</color>", MarkupError::InvalidValue(1)),
+            ("a<icon>", MarkupError::InvalidValue(1)),
+            ("ab<icon=moon>", MarkupError::UnknownIcon(2)),
+            ("<b>a</i>", MarkupError::MismatchedTag(4)),
+            ("a</b>", MarkupError::MismatchedTag(1)),
+            ("<b>a<i>b", MarkupError::UnclosedTag(4)),
+        ];
+        for &(text, ref error) in &cases {
+            assert_eq!(markup.parse(text).as_ref(), Err(error), "{:?}", text);
+        }
+        assert_eq!(
+            MarkupError::UnknownTag(3).to_string(),
+            "unknown tag at byte 3"
+        );
+    }
+}
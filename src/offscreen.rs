@@ -0,0 +1,129 @@
+use std::sync::Arc;
+
+use vulkano::command_buffer::{
+    AutoCommandBuffer, AutoCommandBufferBuilder, CommandBuffer, CommandBufferExecFuture,
+    DynamicState,
+};
+use vulkano::device::{Device, Queue};
+use vulkano::format::{ClearValue, Format, R8G8B8A8Unorm};
+use vulkano::framebuffer::{Framebuffer, RenderPassAbstract, Subpass};
+use vulkano::image::{AttachmentImage, ImageUsage};
+use vulkano::pipeline::viewport::Viewport;
+use vulkano::single_pass_renderpass;
+use vulkano::sync::NowFuture;
+
+use crate::draw::Draw;
+use crate::section::QueuedGlyph;
+use crate::{Coordinates, Error, GpuCache, RegisteredFont, Section};
+
+/// A render pass and pipeline for drawing text into images.
+pub(crate) struct Offscreen {
+    render_pass: Arc<dyn RenderPassAbstract + Send + Sync>,
+    draw: Draw,
+}
+
+impl Offscreen {
+    pub(crate) fn new(device: &Arc<Device>) -> Result<Self, Error> {
+        let render_pass = Arc::new(single_pass_renderpass!(Arc::clone(device),
+            attachments: {
+                color: {
+                    load: Clear,
+                    store: Store,
+                    format: Format::R8G8B8A8Unorm,
+                    samples: 1,
+                }
+            },
+            pass: {
+                color: [color],
+                depth_stencil: {}
+            }
+        )?) as Arc<dyn RenderPassAbstract + Send + Sync>;
+
+        let subpass =
+            Subpass::from(Arc::clone(&render_pass), 0).expect("render pass has one subpass");
+        let draw = Draw::new(device, subpass)?;
+        Ok(Offscreen { render_pass, draw })
+    }
+
+    pub(crate) fn create_image(
+        device: &Arc<Device>,
+        [width, height]: [u32; 2],
+    ) -> Result<Arc<AttachmentImage<R8G8B8A8Unorm>>, Error> {
+        Ok(AttachmentImage::with_usage(
+            Arc::clone(device),
+            [width, height],
+            R8G8B8A8Unorm,
+            ImageUsage {
+                color_attachment: true,
+                transfer_source: true,
+                sampled: true,
+                ..ImageUsage::none()
+            },
+        )?)
+    }
+
+    pub(crate) fn draw<'a, 'font, I>(
+        &mut self,
+        queue: &Arc<Queue>,
+        glyphs: &[QueuedGlyph<'font>],
+        sections: I,
+        fonts: &[RegisteredFont<'font>],
+        cache: &GpuCache<'font>,
+        image: Arc<AttachmentImage<R8G8B8A8Unorm>>,
+        coordinates: Coordinates,
+    ) -> Result<CommandBufferExecFuture<NowFuture, AutoCommandBuffer>, Error>
+    where
+        I: IntoIterator<Item = &'a Section>,
+    {
+        // The image is always drawn with an identity transform, so raw coordinates are treated
+        // as pixels within the image.
+        self.draw.set_coordinates(if coordinates.y_up() {
+            Coordinates::BottomLeft
+        } else {
+            Coordinates::TopLeft
+        });
+
+        let [width, height] = image.dimensions();
+        let framebuffer = Arc::new(
+            Framebuffer::start(Arc::clone(&self.render_pass))
+                .add(image)?
+                .build()?,
+        );
+        let state = DynamicState {
+            line_width: None,
+            viewports: Some(vec![Viewport {
+                origin: [0.0, 0.0],
+                dimensions: [width as f32, height as f32],
+                depth_range: 0.0..1.0,
+            }]),
+            scissors: None,
+        };
+
+        let cmd = AutoCommandBufferBuilder::primary_one_time_submit(
+            Arc::clone(queue.device()),
+            queue.family(),
+        )?
+        .begin_render_pass(
+            framebuffer,
+            false,
+            vec![ClearValue::Float([0.0, 0.0, 0.0, 0.0])],
+        )?;
+        let cmd = self.draw.draw(
+            cmd,
+            glyphs,
+            sections,
+            fonts,
+            cache,
+            &state,
+            [
+                [1.0, 0.0, 0.0, 0.0],
+                [0.0, 1.0, 0.0, 0.0],
+                [0.0, 0.0, 1.0, 0.0],
+                [0.0, 0.0, 0.0, 1.0],
+            ],
+        )?;
+        let cmd = cmd.end_render_pass()?.build()?;
+
+        Ok(cmd.execute(Arc::clone(queue))?)
+    }
+}
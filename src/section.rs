@@ -0,0 +1,161 @@
+use std::ops::Range;
+
+use rusttype::PositionedGlyph;
+
+use crate::{Decorations, FontId};
+
+/// An index for a range of queued glyphs, along with the attributes used to draw them.
+#[derive(Clone, Debug)]
+pub struct Section {
+    pub(crate) color: [f32; 4],
+    pub(crate) outline_color: [f32; 4],
+    pub(crate) outline_width: f32,
+    pub(crate) shadow: Option<Shadow>,
+    pub(crate) gradient: Option<(Gradient, GradientSpace)>,
+    pub(crate) decorations: Decorations,
+    pub(crate) highlights: Vec<(Range<usize>, [f32; 4])>,
+    pub(crate) params: [f32; 4],
+    pub(crate) hidpi_factor: f32,
+    pub(crate) y_up: bool,
+    pub(crate) range: Range<usize>,
+}
+
+/// A glyph queued for drawing, along with any attributes which override those of its section.
+#[derive(Clone, Debug)]
+pub(crate) struct QueuedGlyph<'font> {
+    pub(crate) glyph: PositionedGlyph<'font>,
+    pub(crate) font: FontId,
+    pub(crate) color: Option<[f32; 4]>,
+    pub(crate) decorations: Option<Decorations>,
+    /// The range of the text the glyph was laid out from, relative to the start of its section.
+    /// For glyphs which were queued directly, this is the index of the glyph.
+    pub(crate) source: Range<usize>,
+    /// Whether the glyph is rotated a quarter turn clockwise around its position, for vertical
+    /// text.
+    pub(crate) rotated: bool,
+    /// Whether the glyph is part of vertical text, which is not decorated or highlighted.
+    pub(crate) vertical: bool,
+}
+
+impl<'font> QueuedGlyph<'font> {
+    pub(crate) fn new(glyph: PositionedGlyph<'font>, font: FontId, source: Range<usize>) -> Self {
+        QueuedGlyph {
+            glyph,
+            font,
+            color: None,
+            decorations: None,
+            source,
+            rotated: false,
+            vertical: false,
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub(crate) struct Shadow {
+    pub(crate) offset: [f32; 2],
+    pub(crate) color: [f32; 4],
+    pub(crate) blur: f32,
+}
+
+/// A gradient fill for the text in a `Section`.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Gradient {
+    /// A gradient varying along the line from `start` to `end`.
+    Linear {
+        start: [f32; 2],
+        start_color: [f32; 4],
+        end: [f32; 2],
+        end_color: [f32; 4],
+    },
+    /// A gradient varying with distance from `center`.
+    Radial {
+        center: [f32; 2],
+        center_color: [f32; 4],
+        radius: f32,
+        edge_color: [f32; 4],
+    },
+}
+
+/// The coordinate space in which a `Gradient` is defined.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum GradientSpace {
+    /// Coordinates are relative to the bounding box of the section, with `[0.0, 0.0]` at the top
+    /// left and `[1.0, 1.0]` at the bottom right, whichever way the y axis points. The radius of
+    /// a radial gradient is relative to the size of the bounding box, so it may be stretched into
+    /// an ellipse.
+    Section,
+    /// Coordinates are in logical pixels, the same as the positions of glyphs.
+    Screen,
+}
+
+impl Section {
+    pub(crate) fn new(range: Range<usize>, color: [f32; 4], hidpi_factor: f32, y_up: bool) -> Self {
+        Section {
+            range,
+            hidpi_factor,
+            y_up,
+            color,
+            outline_color: [0.0; 4],
+            outline_width: 0.0,
+            shadow: None,
+            gradient: None,
+            decorations: Decorations::none(),
+            highlights: Vec::new(),
+            params: [0.0; 4],
+        }
+    }
+
+    /// Draw an outline of the given colour around the glyphs in this section. The width is
+    /// measured in logical pixels, and the outline is drawn underneath the fill colour.
+    pub fn with_outline(mut self, color: [f32; 4], width: f32) -> Self {
+        self.outline_color = color;
+        self.outline_width = width.max(0.0);
+        self
+    }
+
+    /// Draw a drop shadow of the given colour behind the glyphs in this section. The offset and
+    /// blur radius are measured in logical pixels. Shadows are drawn underneath all other sections
+    /// in the same call to `GlyphBrush::draw`.
+    pub fn with_shadow(mut self, offset: [f32; 2], color: [f32; 4], blur: f32) -> Self {
+        self.shadow = Some(Shadow {
+            offset,
+            color,
+            blur: blur.max(0.0),
+        });
+        self
+    }
+
+    /// Fill the glyphs in this section with a gradient instead of a flat colour.
+    pub fn with_gradient(mut self, gradient: Gradient, space: GradientSpace) -> Self {
+        self.gradient = Some((gradient, space));
+        self
+    }
+
+    /// Draw lines such as underlines across the glyphs in this section. The lines use the same
+    /// fill, outline and shadow as the glyphs. Underlines and strikethroughs are placed using the
+    /// metrics of fonts added with `GlyphBrush::add_font_bytes`, and estimated for other fonts.
+    pub fn with_decorations(mut self, decorations: Decorations) -> Self {
+        self.decorations = decorations;
+        self
+    }
+
+    /// Draw solid rectangles behind the glyphs laid out from a range of text in this section, for
+    /// example to show a text selection. For sections queued with `GlyphBrush::queue_text` or
+    /// `GlyphBrush::queue_spans`, the range is in bytes of the text, so a logical selection in
+    /// bidirectional text may be drawn as several rectangles, and a ligature is highlighted if
+    /// any of its characters are. For sections queued with `GlyphBrush::queue_glyphs`, the range
+    /// is in glyphs. Each rectangle spans the line height of the font. Highlights are drawn
+    /// underneath all other sections in the same call to `GlyphBrush::draw`.
+    pub fn with_highlight(mut self, range: Range<usize>, color: [f32; 4]) -> Self {
+        self.highlights.push((range, color));
+        self
+    }
+
+    /// Set extra parameters to pass to custom shaders for every glyph in this section. See
+    /// `GlyphBrush::with_shaders` for details.
+    pub fn with_params(mut self, params: [f32; 4]) -> Self {
+        self.params = params;
+        self
+    }
+}
@@ -0,0 +1,152 @@
+use rusttype::{Font, GlyphId, Scale};
+use rustybuzz::{Direction, Face, Language, Script, UnicodeBuffer};
+
+/// Options for shaping text with the OpenType layout tables of a font, set with
+/// `Layout::with_shaping`.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Shaping {
+    pub(crate) script: Option<Script>,
+    pub(crate) language: Option<Language>,
+}
+
+impl Shaping {
+    /// Shape text with the script and language guessed from its contents.
+    pub fn new() -> Self {
+        Shaping::default()
+    }
+
+    /// Shape text using the rules for the given script.
+    pub fn with_script(mut self, script: Script) -> Self {
+        self.script = Some(script);
+        self
+    }
+
+    /// Shape text using the rules for the given language.
+    pub fn with_language(mut self, language: Language) -> Self {
+        self.language = Some(language);
+        self
+    }
+}
+
+/// A glyph produced by shaping a run of text.
+pub(crate) struct ShapedGlyph {
+    pub(crate) id: GlyphId,
+    /// The byte offset of the first character in the run which the glyph was produced from.
+    pub(crate) cluster: usize,
+    /// The horizontal advance, in pixels.
+    pub(crate) advance: f32,
+    /// The offset of the glyph from its pen position, in pixels with y pointing down.
+    pub(crate) offset: [f32; 2],
+}
+
+/// Shape a run of text using a single font, returning `None` if `data` could not be parsed. The
+/// glyphs are returned in logical order, even if the run is right-to-left.
+pub(crate) fn shape(
+    data: &[u8],
+    font: &Font<'_>,
+    scale: Scale,
+    text: &str,
+    rtl: bool,
+    shaping: &Shaping,
+) -> Option<Vec<ShapedGlyph>> {
+    let face = Face::from_slice(data, 0)?;
+
+    let mut buffer = UnicodeBuffer::new();
+    buffer.push_str(text);
+    buffer.set_direction(if rtl {
+        Direction::RightToLeft
+    } else {
+        Direction::LeftToRight
+    });
+    if let Some(script) = shaping.script {
+        buffer.set_script(script);
+    }
+    if let Some(language) = &shaping.language {
+        buffer.set_language(language.clone());
+    }
+    buffer.guess_segment_properties();
+    let output = rustybuzz::shape(&face, &[], buffer);
+
+    // Positions are in font units. Scale them the same way as `rusttype`, which maps the height
+    // from descent to ascent onto the scale.
+    let v_metrics = font.v_metrics_unscaled();
+    let height = v_metrics.ascent - v_metrics.descent;
+    let (scale_x, scale_y) = (scale.x / height, scale.y / height);
+
+    let mut glyphs: Vec<_> = output
+        .glyph_infos()
+        .iter()
+        .zip(output.glyph_positions())
+        .map(|(info, pos)| ShapedGlyph {
+            id: GlyphId(info.codepoint),
+            cluster: info.cluster as usize,
+            advance: pos.x_advance as f32 * scale_x,
+            offset: [
+                pos.x_offset as f32 * scale_x,
+                -pos.y_offset as f32 * scale_y,
+            ],
+        })
+        .collect();
+    if rtl {
+        glyphs.reverse();
+    }
+    Some(glyphs)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    static MONO: &[u8] = include_bytes!("../tests/fonts/DejaVuSansMono.ttf");
+    static SERIF: &[u8] = include_bytes!("../tests/fonts/DejaVuSerif.ttf");
+
+    fn ids(glyphs: &[ShapedGlyph]) -> Vec<GlyphId> {
+        glyphs.iter().map(|gly| gly.id).collect()
+    }
+
+    #[test]
+    fn ligatures_are_shaped_into_one_glyph() {
+        let font = Font::from_bytes(SERIF).unwrap();
+        let scale = Scale::uniform(20.0);
+        let glyphs = shape(SERIF, &font, scale, "fi", false, &Shaping::new()).unwrap();
+        assert_eq!(glyphs.len(), 1);
+        assert_ne!(glyphs[0].id, font.glyph('f').id());
+        assert_eq!(glyphs[0].cluster, 0);
+    }
+
+    #[test]
+    fn right_to_left_runs_are_returned_in_logical_order() {
+        let font = Font::from_bytes(MONO).unwrap();
+        let scale = Scale::uniform(20.0);
+        let glyphs = shape(MONO, &font, scale, "ab", true, &Shaping::new()).unwrap();
+        assert_eq!(ids(&glyphs), [font.glyph('a').id(), font.glyph('b').id()]);
+        let clusters: Vec<usize> = glyphs.iter().map(|gly| gly.cluster).collect();
+        assert_eq!(clusters, [0, 1]);
+    }
+
+    #[test]
+    fn positions_are_scaled_like_rusttype() {
+        let font = Font::from_bytes(MONO).unwrap();
+        let scale = Scale { x: 30.0, y: 20.0 };
+        let advance = font.glyph('q').scaled(scale).h_metrics().advance_width;
+
+        // The combining accent is drawn back over the previous glyph without advancing.
+        let glyphs = shape(MONO, &font, scale, "q\u{301}", false, &Shaping::new()).unwrap();
+        assert_eq!(
+            ids(&glyphs),
+            [font.glyph('q').id(), font.glyph('\u{301}').id()]
+        );
+        assert!((glyphs[0].advance - advance).abs() < 1e-3);
+        assert_eq!(glyphs[0].offset, [0.0, 0.0]);
+        assert_eq!(glyphs[1].advance, 0.0);
+        assert!((glyphs[1].offset[0] + advance).abs() < 1e-3);
+        assert_eq!(glyphs[1].offset[1], 0.0);
+    }
+
+    #[test]
+    fn invalid_data_is_not_shaped() {
+        let font = Font::from_bytes(MONO).unwrap();
+        let scale = Scale::uniform(20.0);
+        assert!(shape(&[0; 4], &font, scale, "a", false, &Shaping::new()).is_none());
+    }
+}